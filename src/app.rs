@@ -1,15 +1,16 @@
-use crate::config::AppConfig;
+use crate::config::{AppConfig, SourceConfig};
 use crate::finder::{Finder, InputStatus};
-use crate::opt_display::OptListItem;
-use crate::parsing::OptText;
+use crate::opt_display::EntryListItem;
+use crate::parsing::Entry;
 use crate::source::Source;
 use color_eyre::eyre::Result;
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use ratatui::{
     prelude::*,
     symbols::border,
-    widgets::{block::Block, Borders, Padding, Paragraph, Tabs},
+    widgets::{block::Block, Borders, Clear, List, ListItem, Padding, Paragraph, Tabs},
 };
+use std::collections::BTreeMap;
 use std::io;
 use std::time::Duration;
 use tracing::debug;
@@ -24,7 +25,11 @@ pub(crate) struct App {
     // To use Nucleo's append optimization and avoid reparsing if pattern hasn't changed
     input_status: InputStatus,
     result_list_state: ListState,
-    selected_item: Option<OptText>,
+    selected_item: Option<Entry>,
+    /// The configuration of each page, from which a page is rebuilt when another release is picked
+    config: &'static AppConfig,
+    /// The highlighted release, while the release picker of the active page is open
+    release_picker: Option<usize>,
     exit: bool,
 }
 
@@ -36,17 +41,17 @@ impl App {
                 .sources
                 .iter()
                 .map(|s| {
-                    Finder::new(
-                        Source::from(s),
-                        config.cache_dir.as_deref(),
-                        config.cache_duration,
-                    )
+                    let source = Source::from(s);
+                    Finder::new(source.clone(), source.cache_config(config))
+                        .with_lock_input(lock_input(config.lock_inputs.as_ref(), s))
                 })
                 .collect(),
             active_page: 0,
             input_status: InputStatus::Change,
             result_list_state: ListState::default(),
             selected_item: None,
+            config,
+            release_picker: None,
             exit: false,
         }
     }
@@ -55,6 +60,12 @@ impl App {
     #[cfg(test)]
     pub(crate) fn new_with_test_data() -> App {
         use crate::test_utils::create_test_finders;
+        use std::sync::LazyLock;
+
+        static CONFIG: LazyLock<AppConfig> = LazyLock::new(|| AppConfig {
+            sources: vec![],
+            ..AppConfig::default()
+        });
 
         App {
             search_string: String::new(),
@@ -63,6 +74,8 @@ impl App {
             input_status: InputStatus::Change,
             result_list_state: ListState::default(),
             selected_item: None,
+            config: &CONFIG,
+            release_picker: None,
             exit: false,
         }
     }
@@ -73,7 +86,7 @@ impl App {
         self.input_status = InputStatus::Unchanged;
     }
 
-    fn get_results(&self, max: Option<usize>) -> Vec<OptText> {
+    fn get_results(&self, max: Option<usize>) -> Vec<Entry> {
         assert!(self.active_page < self.pages.len());
         self.pages[self.active_page].get_results(max)
     }
@@ -83,7 +96,7 @@ impl App {
     fn search_blocking(
         &mut self,
         max: Option<usize>,
-    ) -> std::result::Result<Vec<OptText>, Box<dyn std::any::Any + Send + 'static>> {
+    ) -> std::result::Result<Vec<Entry>, Box<dyn std::any::Any + Send + 'static>> {
         assert!(self.active_page < self.pages.len());
         self.pages[self.active_page].find_blocking(&self.search_string, max)
     }
@@ -128,20 +141,25 @@ impl App {
 
     fn handle_key_event(&mut self, key: KeyEvent) {
         debug!(name: "Handling a key event", key = format!("{key:?}"));
+        if let Some(selected) = self.release_picker {
+            self.handle_release_picker_key(key, selected);
+            self.init_search();
+            return;
+        }
         match (key.code, key.modifiers) {
-            (KeyCode::Right, _) | (KeyCode::Char('l'), KeyModifiers::CONTROL) => {
-                if self.active_page + 1 < self.pages.len() {
-                    self.active_page += 1;
-                    self.input_status = InputStatus::Change;
-                    self.result_list_state.select(None);
-                }
+            (KeyCode::Right, _) | (KeyCode::Char('l'), KeyModifiers::CONTROL)
+                if self.active_page + 1 < self.pages.len() =>
+            {
+                self.active_page += 1;
+                self.input_status = InputStatus::Change;
+                self.result_list_state.select(None);
             }
-            (KeyCode::Left, _) | (KeyCode::Char('h'), KeyModifiers::CONTROL) => {
-                if self.active_page > 0 {
-                    self.active_page -= 1;
-                    self.input_status = InputStatus::Change;
-                    self.result_list_state.select(None);
-                }
+            (KeyCode::Left, _) | (KeyCode::Char('h'), KeyModifiers::CONTROL)
+                if self.active_page > 0 =>
+            {
+                self.active_page -= 1;
+                self.input_status = InputStatus::Change;
+                self.result_list_state.select(None);
             }
             (KeyCode::Down, _) | (KeyCode::Char('j'), KeyModifiers::CONTROL) => {
                 self.result_list_state.next();
@@ -150,6 +168,12 @@ impl App {
                 self.result_list_state.previous();
             }
             (KeyCode::Esc, _) => self.exit = true,
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                let (releases, current) = self.pages[self.active_page].releases();
+                if !releases.is_empty() {
+                    self.release_picker = Some(current.unwrap_or(0));
+                }
+            }
             (KeyCode::Backspace, KeyModifiers::ALT) => {
                 // Clear the search field
                 // KeyModifier CTRL gets picked up as C-h instead
@@ -171,7 +195,7 @@ impl App {
             }
             (KeyCode::Enter, _) => {
                 if let Some(ref item) = self.selected_item {
-                    for u in &item.declared_by_urls {
+                    for u in item.declared_by_urls() {
                         open_url(u);
                     }
                 }
@@ -189,6 +213,60 @@ impl App {
     }
 }
 
+impl App {
+    fn handle_release_picker_key(&mut self, key: KeyEvent, selected: usize) {
+        let (releases, _) = self.pages[self.active_page].releases();
+        let count = releases.len();
+        match (key.code, key.modifiers) {
+            (KeyCode::Down, _) | (KeyCode::Char('j'), KeyModifiers::CONTROL) => {
+                self.release_picker = Some((selected + 1).min(count.saturating_sub(1)));
+            }
+            (KeyCode::Up, _) | (KeyCode::Char('k'), KeyModifiers::CONTROL) => {
+                self.release_picker = Some(selected.saturating_sub(1));
+            }
+            (KeyCode::Enter, _) => {
+                self.release_picker = None;
+                self.switch_release(selected);
+            }
+            (KeyCode::Esc, _) | (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                self.release_picker = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Replace the active page with one for another release of its source, which is loaded from its own cache file
+    fn switch_release(&mut self, index: usize) {
+        let (releases, current) = self.pages[self.active_page].releases();
+        if current == Some(index) {
+            return;
+        }
+        let (Some(config), Some(release)) = (
+            self.config.sources.get(self.active_page),
+            releases.get(index),
+        ) else {
+            return;
+        };
+        let source = Source::with_release(config, Some(release));
+        debug!(%source, "Switching release");
+        // A source pinned by a flake.lock has the locked release first
+        let lock_input =
+            lock_input(self.config.lock_inputs.as_ref(), config).filter(|_| index == 0);
+        let cache = source.cache_config(self.config);
+        self.pages[self.active_page] = Finder::new(source, cache).with_lock_input(lock_input);
+        self.input_status = InputStatus::Change;
+        self.result_list_state.select(None);
+    }
+}
+
+/// The `flake.lock` input that `source` was pinned to
+fn lock_input(
+    lock_inputs: Option<&BTreeMap<String, String>>,
+    source: &SourceConfig,
+) -> Option<String> {
+    lock_inputs?.get(&source.name).cloned()
+}
+
 fn open_url(url: &str) {
     let res = open::that_detached(url);
     debug!(name: "Open url", "{url}, {res:?}");
@@ -219,8 +297,18 @@ impl App {
 
     fn render_results(&mut self, area: Rect, buf: &mut Buffer) {
         let title_text = format!(" {} ", self.pages[self.active_page].name());
-        let version = format!(" {} ", self.pages[self.active_page].version());
-        let instructions = Line::from(vec![
+        let mut version = Line::from(format!(" {} ", self.pages[self.active_page].version()));
+        if self.pages[self.active_page].is_refreshing() {
+            version.spans.insert(0, " Cached, refreshing... ".yellow());
+        } else if self.pages[self.active_page].is_stale() {
+            let label = if self.config.offline {
+                " Outdated cache (offline) "
+            } else {
+                " Outdated cache "
+            };
+            version.spans.insert(0, label.yellow());
+        }
+        let mut instructions = Line::from(vec![
             " Navigation ".into(),
             "Arrows/C-[hjkl], ".yellow().bold(),
             "Quit ".into(),
@@ -230,19 +318,37 @@ impl App {
             "Docs ".into(),
             "<C-o> ".yellow().bold(),
         ]);
+        if !self.pages[self.active_page].releases().0.is_empty() {
+            instructions.push_span("Release ");
+            instructions.push_span("<C-r> ".yellow().bold());
+        }
 
-        let results_block = Block::default()
+        let mut results_block = Block::default()
             .title_top(Line::from(title_text).bold().centered())
-            .title_top(Line::from(version).right_aligned())
+            .title_top(version.right_aligned())
             .title_bottom(instructions.centered())
             .borders(Borders::ALL)
             .border_set(border::THICK)
             .padding(Padding::horizontal(1));
 
-        let results: Vec<OptListItem> = self
+        if let Some(report) = self.pages[self.active_page].parse_report() {
+            if !report.skipped.is_empty() {
+                results_block = results_block.title_top(
+                    Line::from(format!(
+                        " Skipped {} of {} entries, see log ",
+                        report.skipped.len(),
+                        report.entries + report.skipped.len()
+                    ))
+                    .yellow()
+                    .left_aligned(),
+                );
+            }
+        }
+
+        let results: Vec<EntryListItem> = self
             .get_results(None)
             .into_iter()
-            .map(OptListItem::new)
+            .map(EntryListItem::new)
             .collect();
 
         let results_list_builder = ListBuilder::new(|context| {
@@ -259,7 +365,7 @@ impl App {
                 // If the .get(i) call returns None, it's because we used to have more search results
                 // before the search term was changed, and now the selection index is out of bounds.
                 .or(results.last())
-                .map(|s| s.content.clone())
+                .map(EntryListItem::content)
         } else {
             None
         };
@@ -267,6 +373,45 @@ impl App {
         results_list.render(area, buf, &mut self.result_list_state);
     }
 
+    /// A popup in the middle of `area` listing the releases of the active page
+    fn render_release_picker(&self, area: Rect, buf: &mut Buffer, selected: usize) {
+        let (releases, current) = self.pages[self.active_page].releases();
+        let items = releases.iter().enumerate().map(|(i, release)| {
+            let marker = if Some(i) == current { "● " } else { "  " };
+            let item = ListItem::new(format!("{marker}{release}"));
+            if i == selected {
+                item.style(Style::default().yellow().bold())
+            } else {
+                item
+            }
+        });
+        #[allow(clippy::cast_possible_truncation)]
+        let height = releases.len() as u16 + 2;
+        #[allow(clippy::cast_possible_truncation)]
+        let width = releases
+            .iter()
+            .map(|r| r.len() as u16 + 6)
+            .chain(std::iter::once(20))
+            .max()
+            .unwrap_or(20);
+        let [popup] = Layout::vertical([Constraint::Length(height)])
+            .flex(layout::Flex::Center)
+            .areas(area);
+        let [popup] = Layout::horizontal([Constraint::Length(width)])
+            .flex(layout::Flex::Center)
+            .areas(popup);
+
+        let list = List::new(items).block(
+            Block::default()
+                .title(Line::from(" Release ").centered())
+                .title_bottom(Line::from(" <Enter> ").yellow().bold().centered())
+                .borders(Borders::ALL)
+                .border_set(border::THICK),
+        );
+        Clear.render(popup, buf);
+        Widget::render(list, popup, buf);
+    }
+
     fn render_search_field(&self, area: Rect, buf: &mut Buffer) {
         let search_block = Block::default()
             .borders(Borders::ALL)
@@ -297,6 +442,9 @@ impl Widget for &mut App {
         self.render_tabs(chunks[0], buf);
         self.render_results(chunks[1], buf);
         self.render_search_field(chunks[2], buf);
+        if let Some(selected) = self.release_picker {
+            self.render_release_picker(chunks[1], buf, selected);
+        }
     }
 }
 
@@ -348,6 +496,43 @@ mod tests {
         assert!(app.exit);
     }
 
+    #[test]
+    fn pick_release() {
+        static CONFIG: LazyLock<AppConfig> = LazyLock::new(|| AppConfig {
+            sources: vec![SourceConfig {
+                name: "NixOS".to_string(),
+                url: "/nonexistent/{version}/options.json".to_string(),
+                format: crate::config::SourceFormat::OptionsJson,
+                releases: vec!["24.05".to_string(), "24.11".to_string()],
+                ..Default::default()
+            }],
+            cache_dir: None,
+            lock_inputs: Some(BTreeMap::from([(
+                "NixOS".to_string(),
+                "nixpkgs".to_string(),
+            )])),
+            ..AppConfig::default()
+        });
+        let mut app = App::new(&CONFIG);
+        assert_eq!(app.pages[0].name(), "NixOS 24.05 [nixpkgs]");
+
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL));
+        assert_eq!(app.release_picker, Some(0));
+        app.handle_key_event(KeyCode::Down.into());
+        app.handle_key_event(KeyCode::Down.into());
+        assert_eq!(app.release_picker, Some(1));
+        app.handle_key_event(KeyCode::Enter.into());
+        assert_eq!(app.release_picker, None);
+        assert_eq!(app.pages[0].name(), "NixOS 24.11");
+        assert_eq!(app.pages[0].releases().1, Some(1));
+
+        // Esc closes the picker instead of quitting
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL));
+        app.handle_key_event(KeyCode::Esc.into());
+        assert!(!app.exit);
+        assert_eq!(app.pages[0].name(), "NixOS 24.11");
+    }
+
     // Tests against stored test data to ensure search functionality works across all sources
     #[test]
     fn search_each_tab() {
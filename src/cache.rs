@@ -1,9 +1,11 @@
 use crate::config::AppConfig;
 use color_eyre::eyre::{eyre, Result};
 use std::{
+    fmt, io,
     path::{Path, PathBuf},
-    time::Duration,
+    time::{Duration, SystemTime},
 };
+use tracing::{debug, info};
 
 pub(crate) fn initialize_cache_dir(config: &AppConfig) -> Result<()> {
     if let Some(dir) = &config.cache_dir {
@@ -12,9 +14,14 @@ pub(crate) fn initialize_cache_dir(config: &AppConfig) -> Result<()> {
     Ok(())
 }
 
+#[derive(Default)]
 pub(crate) struct CacheConfig {
     pub(crate) file: Option<PathBuf>,
+    /// Read-only caches that are used if `file` isn't fresh, e.g. pre-populated for all users of a host. Refreshed data is stored in `file` instead.
+    pub(crate) system_files: Vec<PathBuf>,
     pub(crate) duration: Option<Duration>,
+    /// Only use cached data, however old, instead of getting data over the network
+    pub(crate) offline: bool,
 }
 
 /// The possible statuses of a cache file
@@ -36,25 +43,220 @@ pub(crate) enum CacheValidity {
     Unusable,
 }
 
+/// The outcome of refreshing outdated cached data
+pub(crate) enum Revalidation<T> {
+    /// The cached data is still current, and can be used for another cache duration
+    Unchanged,
+    Changed(T),
+}
+
+/// Data that needs the network can only come from the cache in offline mode
+#[derive(Debug)]
+pub(crate) struct Offline;
+
+impl fmt::Display for Offline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the data is not cached, and offline mode is on")
+    }
+}
+
+impl std::error::Error for Offline {}
+
+/// An advisory lock on refreshing a cache file, so that only one nox process downloads a source at a time.
+/// It is an OS file lock on a file next to the cache file, which is released when the process exits, even if it crashed.
+pub(crate) struct CacheLock {
+    /// Unlocked when it is closed. The file itself is left behind, since removing it would let another process lock a new file while this one is still locked.
+    _file: std::fs::File,
+}
+
+impl CacheLock {
+    fn open(cache_file: &Path) -> io::Result<std::fs::File> {
+        let mut name = cache_file.as_os_str().to_owned();
+        name.push(".lock");
+        std::fs::File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(PathBuf::from(name))
+    }
+
+    /// Take the lock of `cache_file`, or `None` if another process holds it
+    pub(crate) fn try_acquire(cache_file: &Path) -> io::Result<Option<Self>> {
+        let file = Self::open(cache_file)?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(Self { _file: file })),
+            Err(std::fs::TryLockError::WouldBlock) => Ok(None),
+            Err(std::fs::TryLockError::Error(err)) => Err(err),
+        }
+    }
+
+    /// Take the lock of `cache_file`, waiting for another process to release it
+    pub(crate) fn acquire(cache_file: &Path) -> io::Result<Self> {
+        let file = Self::open(cache_file)?;
+        file.lock()?;
+        Ok(Self { _file: file })
+    }
+}
+
+/// The start of every cache file, which identifies the format of the data after it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CacheHeader {
+    pub(crate) schema: u32,
+    /// The version of nox that wrote the file
+    pub(crate) nox_version: String,
+}
+
+/// Why a cache file can't be read by this version of nox
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum CacheMismatch {
+    /// The file doesn't start with a header, e.g. because it was written before cache files had one
+    NoHeader,
+    Schema {
+        found: CacheHeader,
+        expected: u32,
+    },
+}
+
+impl fmt::Display for CacheMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoHeader => write!(
+                f,
+                "the cache file has no header, so it was written by an older version of nox"
+            ),
+            Self::Schema { found, expected } => write!(
+                f,
+                "the cache file has schema {} (written by nox {}), but this version of nox reads schema {expected}",
+                found.schema, found.nox_version
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CacheMismatch {}
+
+impl CacheHeader {
+    const MAGIC: &[u8; 4] = b"NOXC";
+
+    fn new(schema: u32) -> Self {
+        Self {
+            schema,
+            nox_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    /// The magic number, the schema as 4 little endian bytes, and the nox version prefixed by its length in one byte
+    fn encode(&self) -> Vec<u8> {
+        let version = &self.nox_version.as_bytes()[..self.nox_version.len().min(255)];
+        let mut bytes = Self::MAGIC.to_vec();
+        bytes.extend(self.schema.to_le_bytes());
+        #[allow(clippy::cast_possible_truncation)]
+        bytes.push(version.len() as u8);
+        bytes.extend(version);
+        bytes
+    }
+
+    /// The header at the start of `bytes`, and the data after it
+    fn decode(bytes: &[u8]) -> Result<(Self, &[u8]), CacheMismatch> {
+        let rest = bytes
+            .strip_prefix(Self::MAGIC)
+            .ok_or(CacheMismatch::NoHeader)?;
+        let (schema, rest) = rest
+            .split_first_chunk::<4>()
+            .ok_or(CacheMismatch::NoHeader)?;
+        let (len, rest) = rest.split_first().ok_or(CacheMismatch::NoHeader)?;
+        let (version, rest) = rest
+            .split_at_checked(usize::from(*len))
+            .ok_or(CacheMismatch::NoHeader)?;
+        let header = Self {
+            schema: u32::from_le_bytes(*schema),
+            nox_version: String::from_utf8_lossy(version).into_owned(),
+        };
+        Ok((header, rest))
+    }
+}
+
+/// `data` encoded with bitcode and compressed with zstd, after a header with `schema`
+pub(crate) fn encode<T: bitcode::Encode>(data: &T, schema: u32, level: i32) -> Result<Vec<u8>> {
+    let bitdata = bitcode::encode(data);
+    let mut bytes = CacheHeader::new(schema).encode();
+    zstd::stream::copy_encode(bitdata.as_slice(), &mut bytes, level)?;
+    Ok(bytes)
+}
+
+/// The data of `encode`. Fails with a `CacheMismatch` if it was encoded with another schema.
+pub(crate) fn decode<T: for<'a> bitcode::Decode<'a>>(bytes: &[u8], schema: u32) -> Result<T> {
+    let (header, zstddata) = CacheHeader::decode(bytes)?;
+    if header.schema != schema {
+        return Err(CacheMismatch::Schema {
+            found: header,
+            expected: schema,
+        }
+        .into());
+    }
+    let bitdata = zstd::stream::decode_all(zstddata)?;
+    Ok(bitcode::decode(&bitdata)?)
+}
+
+/// Replace `file` at once, so that other processes never read a partially written file.
+/// Its modification time is set to `modified`, if given.
+pub(crate) fn write_atomically(
+    file: &Path,
+    bytes: &[u8],
+    modified: Option<SystemTime>,
+) -> Result<()> {
+    let dir = file.parent().unwrap_or(Path::new("."));
+    let mut builder = tempfile::Builder::new();
+    // Temporary files are only readable by their owner by default, unlike files written directly
+    #[cfg(unix)]
+    builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o644));
+    let mut tmp = builder.tempfile_in(dir)?;
+    io::Write::write_all(&mut tmp, bytes)?;
+    if let Some(modified) = modified {
+        tmp.as_file().set_modified(modified)?;
+    }
+    tmp.persist(file)?;
+    Ok(())
+}
+
 pub(crate) trait Cacheable {
     type WithData: bitcode::Encode + for<'a> bitcode::Decode<'a>;
     const ZSTD_COMPRESSION_LEVEL: i32 = 0;
+    /// The version of the encoding of `WithData`, which has to be increased whenever it changes.
+    /// Cache files of other schemas are refetched instead of decoded.
+    const CACHE_SCHEMA: u32;
 
     fn get_expensive(&self) -> Result<Self::WithData>;
+
+    /// Like `get_expensive`, but given outdated cached data, e.g. to make a conditional request that skips downloading and parsing data that did not change.
+    fn get_expensive_since(
+        &self,
+        _cached: &Self::WithData,
+    ) -> Result<Revalidation<Self::WithData>> {
+        self.get_expensive().map(Revalidation::Changed)
+    }
     fn cache_valid(&self, data: &Self::WithData) -> CacheValidity;
 
+    /// Whether `get_expensive` uses the network, so that it is skipped in offline mode
+    fn needs_network(&self) -> bool {
+        true
+    }
+
+    /// The time the data behind `get_expensive` last changed, if that can be determined cheaply.
+    /// If known, the cache is fresh exactly when it is newer than this, regardless of the configured cache duration.
+    fn last_modified(&self) -> Option<SystemTime> {
+        None
+    }
+
+    /// Replaces the cache file at once, so that other processes never read a partially written file
     fn store_cache(data: &Self::WithData, cache_file: &Path) -> Result<()> {
-        let bitdata = bitcode::encode(data);
-        let zstddata = zstd::stream::encode_all(bitdata.as_slice(), Self::ZSTD_COMPRESSION_LEVEL)?;
-        std::fs::write(cache_file, zstddata)?;
-        Ok(())
+        let filedata = encode(data, Self::CACHE_SCHEMA, Self::ZSTD_COMPRESSION_LEVEL)?;
+        write_atomically(cache_file, &filedata, None)
     }
 
+    /// Fails with a `CacheMismatch` if the file was written in another format
     fn load_cache(path: &Path) -> Result<Self::WithData> {
-        let zstddata = std::fs::read(path)?;
-        let bitdata = zstd::stream::decode_all(zstddata.as_slice())?;
-        let data = bitcode::decode(&bitdata)?;
-        Ok(data)
+        decode(&std::fs::read(path)?, Self::CACHE_SCHEMA)
     }
 
     /// Returns Ok(status) unless an underlying system error occurs.
@@ -62,15 +264,38 @@ pub(crate) trait Cacheable {
         let Some(ref cache_file) = config.file else {
             return Ok(CacheStatus::Undefined);
         };
+        let system = config.system_files.contains(cache_file);
+        self.file_status(cache_file, config.duration, system)
+    }
+
+    /// The status of `cache_file`, which need not be the configured cache file. `system` is whether it is one of the read-only system caches.
+    fn file_status(
+        &self,
+        cache_file: &Path,
+        duration: Option<Duration>,
+        system: bool,
+    ) -> Result<CacheStatus> {
         if !std::fs::exists(cache_file)? {
             return Ok(CacheStatus::Missing);
         }
         let f = std::fs::File::open(cache_file)?;
-        let Some(max_age) = config.duration else {
+        let last_modified = f.metadata()?.modified()?;
+        // System caches in the Nix store all have this modification time, so their age is unknown. They are updated by rebuilding instead.
+        // Any other file may just have been restored without its modification time, and is treated like an old file.
+        if system && last_modified <= SystemTime::UNIX_EPOCH + Duration::from_secs(1) {
+            return Ok(CacheStatus::Fresh);
+        }
+        if let Some(data_modified) = self.last_modified() {
+            return Ok(if last_modified >= data_modified {
+                CacheStatus::Fresh
+            } else {
+                CacheStatus::Outdated
+            });
+        }
+        let Some(max_age) = duration else {
             return Ok(CacheStatus::Fresh);
         };
 
-        let last_modified = f.metadata()?.modified()?;
         let age = last_modified.elapsed()?;
         Ok(if age < max_age {
             CacheStatus::Fresh
@@ -79,63 +304,235 @@ pub(crate) trait Cacheable {
         })
     }
 
+    /// The best of the configured cache file and the system cache files: The first good one, or else the first usable one
     fn maybe_load_cache(&self, config: &CacheConfig) -> MaybeCache<Self::WithData> {
-        let (Some(cache_path), Ok(status)) = (&config.file, self.cache_status(config)) else {
+        let mut found = MaybeCache::None;
+        let own_file = config.file.iter().map(|file| (file, false));
+        let system_files = config.system_files.iter().map(|file| (file, true));
+        for (cache_path, system) in own_file.chain(system_files) {
+            match self.maybe_load_cache_file(cache_path, config.duration, system) {
+                cache @ MaybeCache::Good(_) => return cache,
+                cache if matches!(found, MaybeCache::None) => found = cache,
+                _ => {}
+            }
+        }
+        found
+    }
+
+    fn maybe_load_cache_file(
+        &self,
+        cache_path: &Path,
+        duration: Option<Duration>,
+        system: bool,
+    ) -> MaybeCache<Self::WithData> {
+        let Ok(status) = self.file_status(cache_path, duration, system) else {
             return MaybeCache::None;
         };
-
-        match status {
-            CacheStatus::Fresh => {
-                if let Ok(data) = Self::load_cache(cache_path) {
-                    match self.cache_valid(&data) {
-                        CacheValidity::Good => return MaybeCache::Good(data),
-                        CacheValidity::Fallback => return MaybeCache::Fallback(data),
-                        CacheValidity::Unusable => (),
-                    }
-                }
+        if !matches!(status, CacheStatus::Fresh | CacheStatus::Outdated) {
+            return MaybeCache::None;
+        }
+        let data = match Self::load_cache(cache_path) {
+            Ok(data) => data,
+            Err(err) => {
+                info!(
+                    ?cache_path,
+                    "Refetching data, because the cache can't be read: {err}"
+                );
+                return MaybeCache::None;
             }
-            CacheStatus::Outdated => return MaybeCache::Outdated,
-            _ => (),
+        };
+
+        match (status, self.cache_valid(&data)) {
+            (_, CacheValidity::Unusable) => MaybeCache::None,
+            (CacheStatus::Outdated, _) => MaybeCache::Outdated { data, system },
+            (_, CacheValidity::Good) => MaybeCache::Good(data),
+            (_, CacheValidity::Fallback) => MaybeCache::Fallback(data),
         }
-        MaybeCache::None
+    }
+
+    /// Mark the cache as fresh again, without rewriting it
+    fn extend_cache(cache_file: &Path) -> Result<()> {
+        std::fs::File::options()
+            .write(true)
+            .open(cache_file)?
+            .set_modified(SystemTime::now())?;
+        Ok(())
     }
 
     fn get_data(&self, config: &CacheConfig) -> Result<Self::WithData> {
-        let maybe_cache = self.maybe_load_cache(config);
-        if let MaybeCache::Good(data) = maybe_cache {
-            return Ok(data);
+        self.refresh(config, self.maybe_load_cache(config))
+            .map(Refreshed::into_inner)
+    }
+
+    /// Like `get_data`, but outdated and fallback caches are passed to `show` right away, before they are refreshed.
+    /// `show` is called again if fresh data replaces the cached data. Its second argument is whether the data is outdated.
+    fn get_data_stale_while_revalidate(
+        &self,
+        config: &CacheConfig,
+        show: &mut dyn FnMut(Self::WithData, bool),
+    ) -> Result<()>
+    where
+        Self::WithData: Clone,
+    {
+        let cache = self.maybe_load_cache(config);
+        let stale_shown = match &cache {
+            MaybeCache::Outdated { data, .. } | MaybeCache::Fallback(data) => {
+                show(data.clone(), true);
+                true
+            }
+            MaybeCache::Good(_) | MaybeCache::None => false,
+        };
+        match self.refresh(config, cache)? {
+            Refreshed::Cached(_) if stale_shown => {}
+            Refreshed::Cached(data) | Refreshed::Fresh(data) => show(data, false),
         }
+        Ok(())
+    }
 
-        if let Ok(data) = self.get_expensive() {
+    /// The data given the result of `maybe_load_cache`, refreshing outdated or fallback data unless offline.
+    /// While another process refreshes the same cache, its old data is used, or if there is none, the other process is waited for.
+    fn refresh(
+        &self,
+        config: &CacheConfig,
+        cache: MaybeCache<Self::WithData>,
+    ) -> Result<Refreshed<Self::WithData>> {
+        if config.offline && self.needs_network() {
+            return cache
+                .into_data()
+                .map(Refreshed::Cached)
+                .ok_or_else(|| Offline.into());
+        }
+        let (cache, _lock) = match (cache, &config.file) {
+            (MaybeCache::Good(data), _) => return Ok(Refreshed::Cached(data)),
+            (cache, None) => (cache, None),
+            (MaybeCache::None, Some(file)) => {
+                let lock = CacheLock::acquire(file);
+                if let Err(err) = &lock {
+                    debug!(?file, "Refreshing without locking the cache: {err}");
+                }
+                // The other process may have refreshed the cache in the meantime
+                match self.maybe_load_cache(config) {
+                    MaybeCache::Good(data) => return Ok(Refreshed::Cached(data)),
+                    cache => (cache, lock.ok()),
+                }
+            }
+            (cache @ (MaybeCache::Outdated { .. } | MaybeCache::Fallback(_)), Some(file)) => {
+                match CacheLock::try_acquire(file) {
+                    Ok(Some(lock)) => (cache, Some(lock)),
+                    Ok(None) => {
+                        debug!(
+                            ?file,
+                            "Another process is refreshing the cache, using it as is"
+                        );
+                        let (MaybeCache::Outdated { data, .. } | MaybeCache::Fallback(data)) =
+                            cache
+                        else {
+                            unreachable!("The cache has data")
+                        };
+                        return Ok(Refreshed::Cached(data));
+                    }
+                    Err(err) => {
+                        debug!(?file, "Refreshing without locking the cache: {err}");
+                        (cache, None)
+                    }
+                }
+            }
+        };
+
+        let (cached, fresh) = match cache {
+            MaybeCache::Good(data) => return Ok(Refreshed::Cached(data)),
+            MaybeCache::Outdated { data, system }
+                if matches!(self.cache_valid(&data), CacheValidity::Good) =>
+            {
+                match self.get_expensive_since(&data) {
+                    Ok(Revalidation::Unchanged) => {
+                        if let Some(cache_path) = &config.file {
+                            // Ignore errors, at worst the data is revalidated again next time.
+                            // Data from a system cache is copied, since those are never written to.
+                            if system {
+                                drop(Self::store_cache(&data, cache_path));
+                            } else {
+                                drop(Self::extend_cache(cache_path));
+                            }
+                        }
+                        return Ok(Refreshed::Cached(data));
+                    }
+                    Ok(Revalidation::Changed(fresh)) => (Some(data), Ok(fresh)),
+                    Err(err) => (Some(data), Err(err)),
+                }
+            }
+            MaybeCache::Outdated { data, .. } | MaybeCache::Fallback(data) => {
+                (Some(data), self.get_expensive())
+            }
+            MaybeCache::None => (None, self.get_expensive()),
+        };
+
+        if let Ok(data) = fresh {
             // Cache is outdated, missing, or doesn't fully match with Self, but we can get fresh data
             if let Some(cache_path) = &config.file {
                 // Update the cache, ignoring any errors
                 drop(Self::store_cache(&data, cache_path));
             }
-            return Ok(data);
+            return Ok(Refreshed::Fresh(data));
         }
 
-        match maybe_cache {
-            MaybeCache::Outdated => {
-                if let Some(cache_path) = &config.file {
-                    if let Ok(data) = Self::load_cache(cache_path) {
-                        match self.cache_valid(&data) {
-                            CacheValidity::Good | CacheValidity::Fallback => return Ok(data),
-                            CacheValidity::Unusable => (),
-                        }
-                    }
-                }
-            }
-            MaybeCache::Good(_) => unreachable!(),
-            MaybeCache::Fallback(data) => return Ok(data),
-            MaybeCache::None => (),
+        // Outdated caches and those that don't fully match are still better than nothing
+        cached
+            .map(Refreshed::Cached)
+            .ok_or_else(|| eyre!("Failed to get fresh data and no valid cache found"))
+    }
+}
+
+/// Where the data returned by `Cacheable::refresh` came from
+pub(crate) enum Refreshed<T> {
+    Cached(T),
+    Fresh(T),
+}
+
+impl<T> Refreshed<T> {
+    fn into_inner(self) -> T {
+        match self {
+            Self::Cached(data) | Self::Fresh(data) => data,
         }
-        Err(eyre!("Failed to get fresh data and no valid cache found"))
     }
 }
+
 pub(crate) enum MaybeCache<T> {
-    Outdated,
+    /// Usable as a fallback, but should be refreshed. `system` is whether it was loaded from a read-only system cache.
+    Outdated {
+        data: T,
+        system: bool,
+    },
     Good(T),
     Fallback(T),
     None,
 }
+
+impl<T> MaybeCache<T> {
+    fn into_data(self) -> Option<T> {
+        match self {
+            Self::Outdated { data, .. } | Self::Good(data) | Self::Fallback(data) => Some(data),
+            Self::None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_lock() -> Result<()> {
+        let tmpdir = tempfile::tempdir().expect("Can create temporary directory");
+        let cache_file = tmpdir.path().join("Source.zst");
+        let lock = CacheLock::try_acquire(&cache_file)?.expect("The lock is free");
+        assert!(CacheLock::try_acquire(&cache_file)?.is_none());
+        drop(lock);
+        assert!(CacheLock::try_acquire(&cache_file)?.is_some());
+
+        // A lock file left behind by a process that exited doesn't hold the lock
+        assert!(tmpdir.path().join("Source.zst.lock").exists());
+        let _lock = CacheLock::acquire(&cache_file)?;
+        Ok(())
+    }
+}
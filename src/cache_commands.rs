@@ -0,0 +1,467 @@
+use crate::cache::{
+    decode, encode, write_atomically, CacheConfig, CacheLock, CacheStatus, Cacheable,
+};
+use crate::cli::OutputFormat;
+use crate::config::{AppConfig, SourceConfig};
+use crate::doctor::{format_age, format_size};
+use crate::source::{Source, SourceData};
+use bitcode::{Decode, Encode};
+use color_eyre::eyre::{bail, ensure, Result, WrapErr};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The version of the format of `CacheBundle`, which has to be increased whenever it changes.
+/// The caches in a bundle have their own schema, which is checked separately.
+const BUNDLE_SCHEMA: u32 = 1;
+
+/// The caches of several sources in one file, as written by `nox cache export`
+#[derive(Encode, Decode)]
+struct CacheBundle {
+    caches: Vec<BundledCache>,
+}
+
+#[derive(Encode, Decode)]
+struct BundledCache {
+    /// The source the cache belongs to, with its configuration except for literal header values, which may be secrets
+    source: Source,
+    /// When the cache was written, in seconds since the Unix epoch, so that it is as fresh after importing as before
+    modified_secs: u64,
+    /// The cache file as written by `Cacheable::store_cache`
+    cache: Vec<u8>,
+}
+
+impl BundledCache {
+    fn read(source: &Source, file: &Path) -> Result<Self> {
+        let modified = std::fs::metadata(file)?.modified()?;
+        Ok(Self {
+            source: source.redacted(),
+            modified_secs: modified.duration_since(UNIX_EPOCH)?.as_secs(),
+            cache: std::fs::read(file)?,
+        })
+    }
+
+    /// The data of the cache, if this version of nox can read it and it belongs to its source
+    fn data(&self) -> Result<SourceData> {
+        let data: SourceData = decode(&self.cache, Source::CACHE_SCHEMA)?;
+        ensure!(
+            data.source == self.source,
+            "the cache belongs to {} with another configuration",
+            data.source
+        );
+        Ok(data)
+    }
+
+    fn modified(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.modified_secs)
+    }
+}
+
+/// The state of the cache of one source, as listed by `nox cache status`
+#[derive(Debug, Serialize)]
+struct CacheReport {
+    source: String,
+    file: PathBuf,
+    /// Whether the file is in one of the `system_cache_dirs`
+    read_only: bool,
+    status: &'static str,
+    size: Option<u64>,
+    age_secs: Option<u64>,
+    version: Option<String>,
+    entries: Option<usize>,
+    /// Why the cache can't be read, if it exists but can't
+    error: Option<String>,
+}
+
+impl CacheReport {
+    fn new(source: &Source, config: &AppConfig, cache_dir: &Path) -> Self {
+        let own_file = source.cache_file(cache_dir);
+        // Sources without a cache of their own use the first system cache there is
+        let system_file = config
+            .system_cache_dirs
+            .iter()
+            .map(|d| source.cache_file(d))
+            .find(|f| f.exists());
+        let (file, read_only) = match system_file {
+            Some(system_file) if !own_file.exists() => (system_file, true),
+            _ => (own_file, false),
+        };
+        let cache = CacheConfig {
+            file: Some(file.clone()),
+            ..source.cache_config(config)
+        };
+        let mut report = Self {
+            source: source.to_string(),
+            file,
+            read_only,
+            status: "missing",
+            size: None,
+            age_secs: None,
+            version: None,
+            entries: None,
+            error: None,
+        };
+        report.status = match source.cache_status(&cache) {
+            Ok(CacheStatus::Fresh) => "fresh",
+            Ok(CacheStatus::Outdated) => "outdated",
+            Ok(CacheStatus::Missing | CacheStatus::Undefined) => return report,
+            Err(err) => {
+                report.error = Some(err.to_string());
+                return report;
+            }
+        };
+        let metadata = std::fs::metadata(&report.file).ok();
+        report.size = metadata.as_ref().map(std::fs::Metadata::len);
+        report.age_secs = metadata
+            .and_then(|m| m.modified().ok())
+            .and_then(|m| m.elapsed().ok())
+            .map(|age| age.as_secs());
+        match Source::load_cache(&report.file) {
+            Ok(data) => {
+                let entries = data.opts.len() + data.packages.len() + data.functions.len();
+                report.version = Some(data.version);
+                report.entries = Some(entries);
+            }
+            Err(err) => report.error = Some(err.to_string()),
+        }
+        report
+    }
+
+    fn to_text(&self) -> String {
+        let mut facts = vec![self.status.to_string()];
+        if self.read_only {
+            facts.push("read-only system cache".to_string());
+        }
+        facts.extend(
+            self.size
+                .map(|s| format_size(usize::try_from(s).unwrap_or(usize::MAX))),
+        );
+        facts.extend(
+            self.age_secs
+                .map(|s| format!("{} old", format_age(Duration::from_secs(s)))),
+        );
+        facts.extend(self.version.clone());
+        facts.extend(self.entries.map(|n| format!("{n} entries")));
+        facts.extend(self.error.as_ref().map(|e| format!("unreadable: {e}")));
+        format!(
+            "{}: {}\n  {}",
+            self.source,
+            facts.join(", "),
+            self.file.display()
+        )
+    }
+}
+
+fn cache_dir(config: &AppConfig) -> Result<&Path> {
+    match &config.cache_dir {
+        Some(dir) => Ok(dir),
+        None => bail!("Caching is disabled in your configuration (use_cache = false)"),
+    }
+}
+
+/// The sources of the configuration, with each of their releases
+fn all_sources(config: &AppConfig) -> Vec<Source> {
+    config
+        .sources
+        .iter()
+        .flat_map(Source::each_release)
+        .collect()
+}
+
+/// List the cache file of each source with its size, age, status, version and number of entries
+pub(crate) fn print_cache_status(config: &AppConfig, output: OutputFormat) -> Result<()> {
+    let dir = cache_dir(config)?;
+    let reports = all_sources(config)
+        .iter()
+        .map(|s| CacheReport::new(s, config, dir))
+        .collect::<Vec<_>>();
+    match output {
+        OutputFormat::Text => {
+            for report in &reports {
+                println!("{}", report.to_text());
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&reports)?),
+    }
+    Ok(())
+}
+
+/// Download and parse the data of the sources named in `names` (or of all sources), and replace their caches.
+/// Never prompts, and returns an error if any source fails, so that it can run unattended.
+pub(crate) fn refresh_caches(config: &AppConfig, names: &[String]) -> Result<()> {
+    let dir = cache_dir(config)?;
+    let is_named = |name: &str, source: &SourceConfig| source.name.eq_ignore_ascii_case(name);
+    let unknown = names
+        .iter()
+        .filter(|n| !config.sources.iter().any(|s| is_named(n, s)))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        bail!("No configured source is named {}", unknown.join(" or "));
+    }
+    ensure!(!config.offline, "Caches can't be refreshed in offline mode");
+
+    let sources = config
+        .sources
+        .iter()
+        .filter(|s| names.is_empty() || names.iter().any(|n| is_named(n, s)))
+        .flat_map(Source::each_release);
+    let mut failed = vec![];
+    for source in sources {
+        let file = source.cache_file(dir);
+        // Wait for other processes refreshing the same source, then download anyway
+        let _lock = CacheLock::acquire(&file)?;
+        let res = source
+            .get_online_data()
+            .and_then(|data| Source::store_cache(&data, &file).map(|()| data));
+        match res {
+            Ok(data) => println!("Refreshed {source}: {}", data.version),
+            Err(err) => {
+                eprintln!("Failed to refresh {source}: {err:#}");
+                failed.push(source.to_string());
+            }
+        }
+    }
+    if !failed.is_empty() {
+        bail!("Failed to refresh {}", failed.join(", "));
+    }
+    Ok(())
+}
+
+/// Pack the caches of all sources of the configuration into `file`, leaving out missing and unreadable caches
+pub(crate) fn export_caches(config: &AppConfig, file: &Path) -> Result<()> {
+    let dir = cache_dir(config)?;
+    let mut caches = vec![];
+    for source in all_sources(config) {
+        let cache_file = source.cache_file(dir);
+        if !cache_file.exists() {
+            continue;
+        }
+        let res = BundledCache::read(&source, &cache_file)
+            .and_then(|cache| Ok((cache.data()?.version, cache)));
+        match res {
+            Ok((version, cache)) => {
+                println!("Exported {source}: {version}");
+                caches.push(cache);
+            }
+            Err(err) => eprintln!("Skipped {source}: {err:#}"),
+        }
+    }
+    ensure!(
+        !caches.is_empty(),
+        "There are no caches to export in {}",
+        dir.display()
+    );
+    let count = caches.len();
+    let bytes = encode(
+        &CacheBundle { caches },
+        BUNDLE_SCHEMA,
+        Source::ZSTD_COMPRESSION_LEVEL,
+    )?;
+    write_atomically(file, &bytes, None)?;
+    println!("Wrote {count} caches to {}", file.display());
+    Ok(())
+}
+
+/// Install the caches in `file`, as written by `nox cache export`, into the cache directory, keeping their age.
+/// Every cache is checked before any is installed, and caches that are at least as recent here are kept.
+pub(crate) fn import_caches(config: &AppConfig, file: &Path) -> Result<()> {
+    let dir = cache_dir(config)?;
+    let bundle: CacheBundle = decode(&std::fs::read(file)?, BUNDLE_SCHEMA).wrap_err_with(|| {
+        format!(
+            "{} is not a cache bundle that this version of nox can read",
+            file.display()
+        )
+    })?;
+    for cache in &bundle.caches {
+        cache
+            .data()
+            .wrap_err_with(|| format!("The cache of {} can't be imported", cache.source))?;
+    }
+
+    std::fs::create_dir_all(dir)?;
+    for cache in bundle.caches {
+        let target = cache.source.cache_file(dir);
+        let _lock = CacheLock::acquire(&target)?;
+        let local_modified = std::fs::metadata(&target).and_then(|m| m.modified());
+        if local_modified.is_ok_and(|m| m >= cache.modified()) {
+            println!(
+                "Kept {}: the cache here is at least as recent",
+                cache.source
+            );
+            continue;
+        }
+        write_atomically(&target, &cache.cache, Some(cache.modified()))?;
+        let name = &cache.source.inner.name;
+        if config.sources.iter().any(|s| s.name == *name) {
+            println!("Imported {}", cache.source);
+        } else {
+            println!("Imported {} (no source is named {name} here)", cache.source);
+        }
+    }
+    Ok(())
+}
+
+/// Delete the cache files in the cache directory that belong to no source of the configuration (including any releases picked from a flake.lock)
+pub(crate) fn prune_caches(config: &AppConfig, dry_run: bool) -> Result<()> {
+    let dir = cache_dir(config)?;
+    let used = all_sources(config)
+        .iter()
+        .map(|s| s.cache_file(dir))
+        .collect::<HashSet<_>>();
+    let unused = unused_cache_files(dir, &used)?;
+    if unused.is_empty() {
+        println!("No unused cache files in {}", dir.display());
+    }
+    for file in unused {
+        if dry_run {
+            println!("Would delete {}", file.display());
+        } else {
+            std::fs::remove_file(&file)?;
+            println!("Deleted {}", file.display());
+        }
+    }
+    Ok(())
+}
+
+/// The cache files in `dir` that are not in `used`, and the lock files left next to them. Other files are left alone, in case the cache directory is shared.
+fn unused_cache_files(dir: &Path, used: &HashSet<PathBuf>) -> Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut unused = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let cache_file = if path.extension().is_some_and(|e| e == "lock") {
+            path.with_extension("")
+        } else {
+            path.clone()
+        };
+        if path.is_file()
+            && cache_file.extension().is_some_and(|e| e == "zst")
+            && !used.contains(&cache_file)
+        {
+            unused.push(path);
+        }
+    }
+    unused.sort();
+    Ok(unused)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{local_options_source, outdated};
+    use tempfile::tempdir;
+
+    #[test]
+    fn refresh_and_prune() -> Result<()> {
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        let cache_dir = tmpdir.path().join("cache");
+        std::fs::create_dir(&cache_dir)?;
+        let config = AppConfig {
+            sources: vec![local_options_source(tmpdir.path())],
+            cache_dir: Some(cache_dir.clone()),
+            ..AppConfig::default()
+        };
+        let source = Source::from(&config.sources[0]);
+
+        let report = CacheReport::new(&source, &config, &cache_dir);
+        assert_eq!(report.status, "missing");
+        refresh_caches(&config, &[])?;
+        let report = CacheReport::new(&source, &config, &cache_dir);
+        assert_eq!(report.status, "fresh");
+        assert_eq!(report.entries, Some(1));
+        assert!(refresh_caches(&config, &["Other".to_string()]).is_err());
+
+        let removed = cache_dir.join("Removed source.zst");
+        let removed_lock = cache_dir.join("Removed source.zst.lock");
+        let unrelated = cache_dir.join("notes.txt");
+        std::fs::write(&removed, "")?;
+        std::fs::write(&removed_lock, "")?;
+        std::fs::write(&unrelated, "")?;
+        prune_caches(&config, true)?;
+        assert!(removed.exists());
+        prune_caches(&config, false)?;
+        assert!(!removed.exists());
+        assert!(!removed_lock.exists());
+        assert!(unrelated.exists());
+        assert!(report.file.exists());
+        assert!(cache_dir.join("Local.zst.lock").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn export_and_import() -> Result<()> {
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        let source_config = local_options_source(tmpdir.path());
+        let config_with_cache = |dir: &str| AppConfig {
+            sources: vec![source_config.clone()],
+            cache_dir: Some(tmpdir.path().join(dir)),
+            ..AppConfig::default()
+        };
+        let (online, airgapped) = (config_with_cache("online"), config_with_cache("airgapped"));
+        let bundle = tmpdir.path().join("caches.bundle");
+        assert!(
+            export_caches(&online, &bundle).is_err(),
+            "Nothing is cached"
+        );
+
+        let online_dir = online.cache_dir.as_deref().expect("Caching is enabled");
+        std::fs::create_dir(online_dir)?;
+        refresh_caches(&online, &[])?;
+        let source = Source::from(&source_config);
+        let cache_file = CacheReport::new(&source, &online, online_dir).file;
+        let modified = outdated(&cache_file)?;
+        let exported = CacheReport::new(&source, &online, online_dir);
+        export_caches(&online, &bundle)?;
+
+        import_caches(&airgapped, &bundle)?;
+        let dir = airgapped.cache_dir.as_deref().expect("Caching is enabled");
+        let imported = CacheReport::new(&source, &airgapped, dir);
+        assert_eq!(imported.status, exported.status);
+        assert_eq!(imported.entries, Some(1));
+        assert_eq!(
+            std::fs::metadata(&imported.file)?
+                .modified()?
+                .duration_since(UNIX_EPOCH)?
+                .as_secs(),
+            modified.duration_since(UNIX_EPOCH)?.as_secs()
+        );
+
+        assert!(import_caches(&airgapped, &cache_file).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn export_leaves_out_header_values() -> Result<()> {
+        use crate::config::HttpHeader;
+
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        let config = AppConfig {
+            sources: vec![SourceConfig {
+                headers: vec![HttpHeader {
+                    name: "Authorization".to_string(),
+                    value: Some("Bearer s3cret".to_string()),
+                    ..Default::default()
+                }],
+                ..local_options_source(tmpdir.path())
+            }],
+            cache_dir: Some(tmpdir.path().join("cache")),
+            ..AppConfig::default()
+        };
+        std::fs::create_dir(tmpdir.path().join("cache"))?;
+        refresh_caches(&config, &[])?;
+        let file = tmpdir.path().join("caches.bundle");
+        export_caches(&config, &file)?;
+
+        // Both the bundle and the cache in it are compressed, so their decompressed contents are searched
+        let bundle: CacheBundle = decode(&std::fs::read(&file)?, BUNDLE_SCHEMA)?;
+        let cache = &bundle.caches[0];
+        assert_eq!(cache.source.inner.headers[0].name, "Authorization");
+        let contents = [bitcode::encode(&bundle), bitcode::encode(&cache.data()?)].concat();
+        assert!(!contents.windows(6).any(|w| w == b"s3cret"));
+        Ok(())
+    }
+}
@@ -1,6 +1,11 @@
 use crate::{
     app::App,
-    config::{default_config_file, default_config_toml, AppConfig, UserConfig},
+    cache_commands::{
+        export_caches, import_caches, print_cache_status, prune_caches, refresh_caches,
+    },
+    config::{default_config_file, default_config_toml, AppConfig, SourceFormat, UserConfig},
+    diff::print_diff,
+    doctor::run_doctor,
     tui,
 };
 use clap::{Parser, Subcommand, ValueEnum};
@@ -16,6 +21,9 @@ pub(crate) struct Cli {
     pub(crate) config: Option<PathBuf>,
     #[arg(short, long, value_name = "FILE")]
     pub(crate) log_file: Option<PathBuf>,
+    /// Only use cached data, without touching the network
+    #[arg(long)]
+    pub(crate) offline: bool,
 
     #[command(subcommand)]
     command: Option<Commands>,
@@ -25,10 +33,25 @@ impl Cli {
     pub(crate) fn run(self, config: &'static AppConfig) -> Result<()> {
         match self.command {
             Some(Commands::ClearCache) => clear_cache(config),
+            Some(Commands::Cache { command }) => match command {
+                CacheCommand::Status { output } => print_cache_status(config, output),
+                CacheCommand::Refresh { sources } => refresh_caches(config, &sources),
+                CacheCommand::Prune { dry_run } => prune_caches(config, dry_run),
+                CacheCommand::Export { file } => export_caches(config, &file),
+                CacheCommand::Import { file } => import_caches(config, &file),
+            },
             Some(Commands::PrintConfig {
                 write,
                 config_to_print,
             }) => print_config(write, config_to_print, config, self.config.as_ref()),
+            Some(Commands::Diff {
+                old,
+                new,
+                fresh,
+                format,
+                output,
+            }) => print_diff(config, &old, &new, fresh, format, output),
+            Some(Commands::Doctor { sources }) => run_doctor(config, &sources),
             None => {
                 debug!("Application started");
                 let mut terminal = tui::init()?;
@@ -42,6 +65,11 @@ impl Cli {
 enum Commands {
     /// Delete existing cache files
     ClearCache,
+    /// Inspect, refresh or prune the cache files of individual sources
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
     /// Print the default configuration for nox
     PrintConfig {
         /// Write the default configuration to the default config location, or the path given to `--config` if set
@@ -49,6 +77,67 @@ enum Commands {
         write: bool,
         config_to_print: Option<PrintableConfig>,
     },
+    /// List the options that were added, removed or changed (in type or default) between two versions of a source
+    Diff {
+        /// The old version: The name of a configured source, which is read from its cache if there is one, with `@release` to pick one of its releases (e.g. "NixOS@24.05"); or a URL or path
+        old: String,
+        /// The new version, in the same forms as OLD
+        new: String,
+        /// Get fresh data for NEW instead of reading its cache, e.g. to compare the cache of a source with the current data
+        #[arg(long)]
+        fresh: bool,
+        /// The format of the data at URLs and paths given as OLD or NEW
+        #[arg(long, value_enum, default_value_t)]
+        format: SourceFormat,
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+    /// Check that each source can be downloaded and parsed, and report on its version, links and cache. Exits with an error if any source fails
+    Doctor {
+        /// The names of the sources to check, instead of all of them
+        sources: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// List the cache file of each source, with its size, age, status, version and number of entries
+    Status {
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+    /// Download and parse the data of sources again and replace their caches, however fresh they are. Never prompts, so it can run from e.g. a systemd timer, and exits with an error if any source fails
+    Refresh {
+        /// Only refresh the source with this name, with all its releases. Can be given several times
+        #[arg(short, long = "source", value_name = "NAME")]
+        sources: Vec<String>,
+    },
+    /// Delete the cache files of sources that are no longer configured
+    Prune {
+        /// List the files that would be deleted, without deleting them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Pack the caches of all sources into one file, e.g. to copy them to a machine without internet access
+    Export {
+        /// The file to write the caches to
+        file: PathBuf,
+    },
+    /// Check the caches in a file written by `nox cache export`, and install them into the cache directory with their original age. Caches that are newer here are kept
+    Import {
+        /// The file to read the caches from
+        file: PathBuf,
+    },
+}
+
+/// How to print the results of a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default)]
+pub(crate) enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// JSON, for further processing
+    Json,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Default)]
@@ -69,7 +158,7 @@ fn print_config(
 ) -> Result<()> {
     let toml = match config_to_print.unwrap_or_default() {
         PrintableConfig::Default => default_config_toml(),
-        PrintableConfig::Current => UserConfig::from(config.clone()).to_toml()?,
+        PrintableConfig::Current => UserConfig::from(config.clone()).redacted().to_toml()?,
     };
 
     println!("{toml}");
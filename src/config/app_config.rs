@@ -3,6 +3,7 @@ use super::{
     user_config::UserConfig,
     SourceConfig,
 };
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone)]
@@ -11,8 +12,14 @@ pub(crate) struct AppConfig {
     pub(crate) sources: Vec<SourceConfig>,
     pub(crate) cache_duration: Option<std::time::Duration>,
     pub(crate) cache_dir: Option<PathBuf>,
+    /// Read-only caches that are used if there is no fresh cache in `cache_dir`
+    pub(crate) system_cache_dirs: Vec<PathBuf>,
     pub(crate) log_level: String,
     pub(crate) log_file: Option<PathBuf>,
+    /// The `flake.lock` input that each source was pinned to, by source name, if `use_flake_lock` is set
+    pub(crate) lock_inputs: Option<BTreeMap<String, String>>,
+    /// Only use cached data, see [`crate::cache::CacheConfig::offline`]
+    pub(crate) offline: bool,
 }
 
 impl From<UserConfig> for AppConfig {
@@ -29,12 +36,15 @@ impl From<UserConfig> for AppConfig {
             } else {
                 None
             },
+            system_cache_dirs: value.system_cache_dirs,
             log_level: value.log_level,
             log_file: if value.enable_logging {
                 Some(value.log_file)
             } else {
                 None
             },
+            lock_inputs: value.use_flake_lock.then(BTreeMap::new),
+            offline: value.offline,
         }
     }
 }
@@ -47,9 +57,12 @@ impl From<AppConfig> for UserConfig {
             auto_refresh_cache: value.cache_duration.is_some(),
             cache_duration: value.cache_duration.unwrap_or_default(),
             cache_dir: value.cache_dir.unwrap_or_else(default_cache_dir),
+            system_cache_dirs: value.system_cache_dirs,
             enable_logging: value.log_file.is_some(),
             log_level: value.log_level,
             log_file: value.log_file.unwrap_or_else(default_log_file),
+            use_flake_lock: value.lock_inputs.is_some(),
+            offline: value.offline,
         }
     }
 }
@@ -0,0 +1,312 @@
+use super::SourceConfig;
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use lazy_regex::{regex_captures, regex_is_match};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// A direct input of a flake, as locked in its `flake.lock`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct LockedInput {
+    /// The name of the input in the flake, e.g. "nixpkgs"
+    name: String,
+    /// The repository the input is fetched from, or the name of the input if that isn't known
+    repo: String,
+    /// The release of the branch the input follows, e.g. "24.11" for "nixos-24.11", or None for a development branch
+    release: Option<String>,
+}
+
+// The subset of the lock file format that is needed, see https://nix.dev/manual/nix/latest/command-ref/new-cli/nix3-flake.html#lock-files
+#[derive(Deserialize)]
+struct LockFile {
+    nodes: HashMap<String, Node>,
+    root: String,
+}
+
+#[derive(Deserialize)]
+struct Node {
+    #[serde(default)]
+    inputs: BTreeMap<String, InputRef>,
+    original: Option<Original>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum InputRef {
+    /// The name of the node the input is locked to
+    Node(String),
+    /// An input that follows the input at this path from the root, e.g. `home-manager.inputs.nixpkgs.follows = "nixpkgs"`
+    Follows(Vec<String>),
+}
+
+/// The flake reference as written in `flake.nix`
+#[derive(Deserialize)]
+struct Original {
+    repo: Option<String>,
+    /// The name of an indirect flake reference, like `nixpkgs` in "nixpkgs/nixos-24.11"
+    id: Option<String>,
+    #[serde(rename = "ref")]
+    git_ref: Option<String>,
+    url: Option<String>,
+}
+
+impl LockFile {
+    fn node(&self, input: &InputRef) -> Option<&Node> {
+        self.resolve(input, self.nodes.len())
+    }
+
+    /// `depth` bounds chains of follows, which are at most as long as there are nodes, unless they are cyclic
+    fn resolve(&self, input: &InputRef, depth: usize) -> Option<&Node> {
+        match input {
+            InputRef::Node(name) => self.nodes.get(name),
+            InputRef::Follows(path) => {
+                let depth = depth.checked_sub(1)?;
+                let mut node = self.nodes.get(&self.root)?;
+                for name in path {
+                    node = self.resolve(node.inputs.get(name)?, depth)?;
+                }
+                Some(node)
+            }
+        }
+    }
+}
+
+/// The `flake.lock` in `dir` or the closest of its parent directories, if any
+pub(super) fn find(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|d| d.join("flake.lock"))
+        .find(|f| f.is_file())
+}
+
+pub(super) fn read(file: &Path) -> Result<Vec<LockedInput>> {
+    let json = std::fs::read_to_string(file)?;
+    parse(&json).wrap_err_with(|| {
+        format!(
+            "Can't read the inputs of {}. Set use_flake_lock = false to ignore it",
+            file.display()
+        )
+    })
+}
+
+fn parse(json: &str) -> Result<Vec<LockedInput>> {
+    let lock: LockFile = serde_json::from_str(json)?;
+    let root = lock
+        .nodes
+        .get(&lock.root)
+        .ok_or_else(|| eyre!("The root node {} is missing", lock.root))?;
+    Ok(root
+        .inputs
+        .iter()
+        .filter_map(|(name, input)| {
+            let original = lock.node(input)?.original.as_ref()?;
+            let release = original
+                .git_ref
+                .as_deref()
+                .or(original.url.as_deref())
+                .and_then(|r| regex_captures!(r"(?:^|\D)(\d{2}\.\d{2})(?:\D|$)", r))
+                .map(|(_, release)| release.to_string());
+            Some(LockedInput {
+                name: name.clone(),
+                repo: original
+                    .repo
+                    .clone()
+                    .or_else(|| original.id.clone())
+                    .unwrap_or_else(|| name.clone()),
+                release,
+            })
+        })
+        .collect())
+}
+
+/// Where the manuals of a project are published
+struct Manuals {
+    repo: &'static str,
+    /// The url that all of its manuals start with
+    base: &'static str,
+    /// The path below `base` of the manual of the development branch
+    development: &'static str,
+    /// If there are manuals of each release: The path below `base` that is replaced, and its replacement with a `{version}` placeholder
+    release: Option<(&'static str, &'static str)>,
+}
+
+const MANUALS: [Manuals; 3] = [
+    Manuals {
+        repo: "nixpkgs",
+        base: "https://nixos.org/manual/nixos/",
+        development: "unstable/",
+        release: Some(("stable/", "{version}/")),
+    },
+    Manuals {
+        repo: "home-manager",
+        base: "https://nix-community.github.io/home-manager/",
+        development: "",
+        release: Some(("", "release-{version}/")),
+    },
+    Manuals {
+        repo: "nix-darwin",
+        base: "https://nix-darwin.github.io/nix-darwin/",
+        development: "",
+        release: None,
+    },
+];
+
+/// Point the sources that document an input of the flake at the release it is locked to.
+/// Returns the name of the input that each of those sources follows, by the name of the source.
+pub(super) fn pin_sources(
+    sources: &mut [SourceConfig],
+    inputs: &[LockedInput],
+) -> BTreeMap<String, String> {
+    let mut pinned = BTreeMap::new();
+    for source in sources {
+        if let Some(input) = inputs.iter().find(|i| pin(source, i)) {
+            pinned.insert(source.name.clone(), input.name.clone());
+        }
+    }
+    pinned
+}
+
+/// Make the release of `input` the one `source` loads, if `source` documents the project of `input`.
+/// Sources with releases get it picked, and other sources are templated with it as their only release.
+fn pin(source: &mut SourceConfig, input: &LockedInput) -> bool {
+    let Some(manuals) = MANUALS.iter().find(|m| m.repo == input.repo) else {
+        return false;
+    };
+    let Some(path) = source.url.strip_prefix(manuals.base) else {
+        return false;
+    };
+    // Manuals of a specific release are left as configured
+    let is_release_specific = |path: &str| regex_is_match!(r"\d{2}\.\d{2}", path);
+
+    let Some(release) = &input.release else {
+        return source.releases.is_empty()
+            && path.starts_with(manuals.development)
+            && !is_release_specific(path);
+    };
+    if !source.releases.is_empty() {
+        let index = source
+            .releases
+            .iter()
+            .position(|r| r == release)
+            .or_else(|| source.releases.iter().position(|r| r.contains(release)));
+        let release = index.map_or_else(|| release.clone(), |i| source.releases.remove(i));
+        source.releases.insert(0, release);
+        return true;
+    }
+    let Some((replaced, template)) = manuals.release else {
+        return false;
+    };
+    if !path.starts_with(replaced) || is_release_specific(path) {
+        return false;
+    }
+    let retarget = |url: &str| {
+        url.strip_prefix(manuals.base)
+            .and_then(|path| path.strip_prefix(replaced))
+            .map(|rest| format!("{}{template}{rest}", manuals.base))
+    };
+    source.url = retarget(&source.url).expect("The url starts with the replaced path");
+    if let Some(version_url) = &source.version_url {
+        source.version_url = Some(retarget(version_url).unwrap_or_else(|| version_url.clone()));
+    }
+    source.releases = vec![release.clone()];
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::consts::{HOMEMANAGER, NIXOS_UNSTABLE, NIX_DARWIN, NIX_OS};
+
+    const LOCK: &str = r#"{
+  "nodes": {
+    "home-manager": {
+      "inputs": { "nixpkgs": ["nixpkgs"] },
+      "locked": { "owner": "nix-community", "repo": "home-manager", "rev": "a9953635d7f34e7358d5189751110f87e3ac17da", "type": "github" },
+      "original": { "owner": "nix-community", "ref": "release-24.11", "repo": "home-manager", "type": "github" }
+    },
+    "darwin": {
+      "locked": { "owner": "LnL7", "repo": "nix-darwin", "rev": "5d40c5f1e5a2ba1ff1bd3ffa8a8e3bad3e4b4d2e", "type": "github" },
+      "original": { "owner": "LnL7", "repo": "nix-darwin", "type": "github" }
+    },
+    "nixpkgs": {
+      "locked": { "owner": "NixOS", "repo": "nixpkgs", "rev": "d29ab98cd4a70a387b8ceea3e930b3340d41ac5a", "type": "github" },
+      "original": { "owner": "NixOS", "ref": "nixos-24.11", "repo": "nixpkgs", "type": "github" }
+    },
+    "root": {
+      "inputs": { "darwin": "darwin", "home-manager": "home-manager", "nixpkgs": "nixpkgs", "pkgs": ["home-manager", "nixpkgs"] }
+    }
+  },
+  "root": "root",
+  "version": 7
+}"#;
+
+    #[test]
+    fn pin_sources_to_lock_file() -> Result<()> {
+        let inputs = parse(LOCK)?;
+        assert_eq!(
+            inputs,
+            vec![
+                LockedInput {
+                    name: "darwin".to_string(),
+                    repo: "nix-darwin".to_string(),
+                    release: None,
+                },
+                LockedInput {
+                    name: "home-manager".to_string(),
+                    repo: "home-manager".to_string(),
+                    release: Some("24.11".to_string()),
+                },
+                LockedInput {
+                    name: "nixpkgs".to_string(),
+                    repo: "nixpkgs".to_string(),
+                    release: Some("24.11".to_string()),
+                },
+                LockedInput {
+                    name: "pkgs".to_string(),
+                    repo: "nixpkgs".to_string(),
+                    release: Some("24.11".to_string()),
+                },
+            ]
+        );
+
+        let mut templated = NIX_OS.clone();
+        templated.name = "NixOS releases".to_string();
+        templated.url = "https://nixos.org/manual/nixos/{version}/options".to_string();
+        templated.releases = vec!["24.05".to_string(), "24.11".to_string()];
+        let mut sources = vec![
+            NIX_DARWIN.clone(),
+            NIX_OS.clone(),
+            NIXOS_UNSTABLE.clone(),
+            HOMEMANAGER.clone(),
+            templated,
+        ];
+        let pinned = pin_sources(&mut sources, &inputs);
+        assert_eq!(
+            pinned.into_iter().collect::<Vec<_>>(),
+            [
+                ("Home Manager", "home-manager"),
+                ("Nix-Darwin", "darwin"),
+                ("NixOS", "nixpkgs"),
+                ("NixOS releases", "nixpkgs"),
+            ]
+            .map(|(s, i)| (s.to_string(), i.to_string()))
+        );
+        assert_eq!(sources[0], *NIX_DARWIN);
+        assert_eq!(
+            sources[1].url,
+            "https://nixos.org/manual/nixos/{version}/options"
+        );
+        assert_eq!(sources[1].releases, ["24.11"]);
+        // The unstable manual doesn't match a release
+        assert_eq!(sources[2], *NIXOS_UNSTABLE);
+        assert_eq!(
+            sources[3].for_release("24.11").url,
+            "https://nix-community.github.io/home-manager/release-24.11/options.xhtml"
+        );
+        assert_eq!(
+            sources[3].for_release("24.11").version_url.as_deref(),
+            Some("https://nix-community.github.io/home-manager/release-24.11/")
+        );
+        assert_eq!(sources[4].releases, ["24.11", "24.05"]);
+        Ok(())
+    }
+}
@@ -1,13 +1,17 @@
 use crate::cli::Cli;
 use color_eyre::eyre::Result;
+use tracing::warn;
 
 mod app_config;
 pub(crate) mod consts;
+mod flake_lock;
 mod project_paths;
 mod user_config;
 pub(crate) use app_config::AppConfig;
 pub(crate) use project_paths::default_config_file;
-pub(crate) use user_config::{default_config_toml, SourceConfig, UserConfig};
+pub(crate) use user_config::{
+    default_config_toml, HttpHeader, SourceConfig, SourceFormat, UrlRewrite, UserConfig,
+};
 
 pub(crate) fn initialize(cli: &Cli) -> Result<AppConfig> {
     // Build user config from config file and possible environment variables
@@ -21,6 +25,30 @@ pub(crate) fn initialize(cli: &Cli) -> Result<AppConfig> {
     if let Some(log_file) = &cli.log_file {
         user_config.log_file.clone_from(log_file);
     }
+    if cli.offline {
+        user_config.offline = true;
+    }
 
     Ok(AppConfig::from(user_config))
 }
+
+/// Search the documentation of the releases the flake of the current project is locked to.
+/// A `flake.lock` that can't be read is only logged, since it may belong to an unrelated parent directory.
+pub(crate) fn pin_to_flake_lock(config: &mut AppConfig) {
+    let Some(lock_inputs) = &mut config.lock_inputs else {
+        return;
+    };
+    let lock_file = std::env::current_dir()
+        .ok()
+        .and_then(|dir| flake_lock::find(&dir));
+    let Some(lock_file) = lock_file else {
+        return;
+    };
+    match flake_lock::read(&lock_file) {
+        Ok(inputs) => *lock_inputs = flake_lock::pin_sources(&mut config.sources, &inputs),
+        Err(err) => warn!(
+            "Ignoring {}, the sources are not pinned: {err}",
+            lock_file.display()
+        ),
+    }
+}
@@ -1,9 +1,11 @@
 use bitcode::{Decode, Encode};
-use color_eyre::eyre::Result;
+use clap::ValueEnum;
+use color_eyre::eyre::{ensure, eyre, Result};
 use figment::{
     providers::{Env, Format, Serialized, Toml},
     Figment,
 };
+use lazy_regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
@@ -14,6 +16,7 @@ use super::{
 };
 
 #[derive(PartialEq, Debug, Deserialize, Serialize)]
+#[allow(clippy::struct_excessive_bools)]
 pub(crate) struct UserConfig {
     /// Order matters
     pub(super) sources: Vec<SourceConfig>,
@@ -22,22 +25,188 @@ pub(crate) struct UserConfig {
     #[serde(with = "humantime_serde")]
     pub(super) cache_duration: std::time::Duration,
     pub(super) cache_dir: PathBuf,
+    /// Read-only cache directories that are checked after `cache_dir`, e.g. pre-populated for all users
+    pub(super) system_cache_dirs: Vec<PathBuf>,
     pub(super) enable_logging: bool,
     /// The directives syntax: <https://docs.rs/tracing-subscriber/latest/tracing_subscriber/filter/struct.EnvFilter.html#example-syntax>
     pub(super) log_level: String,
     pub(super) log_file: PathBuf,
+    /// Whether to pin sources to the releases locked in the `flake.lock` of the current directory or its parents
+    pub(super) use_flake_lock: bool,
+    /// Whether to only use cached data, without touching the network
+    pub(super) offline: bool,
 }
 
 // Source specification loaded from user config.
 // Combine with global cache config to get an actual source.
-#[derive(Debug, Clone, Encode, Decode, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Encode, Decode, PartialEq, Deserialize, Serialize)]
 pub(crate) struct SourceConfig {
     /// The name/title of the source
     pub(crate) name: String,
-    /// The url with data to parse
+    /// The url or local file path with data to parse
     pub(crate) url: String,
     /// An optional url from which to try to parse the version number for the source, if it's not found on the main data page
     pub(crate) version_url: Option<String>,
+    /// The format of the data found at `url`
+    #[serde(default, skip_serializing_if = "SourceFormat::is_default")]
+    pub(crate) format: SourceFormat,
+    /// A shell command whose output is used as the data of the source instead of the contents of `url`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) command: Option<String>,
+    /// How long to wait for `command` to finish before giving up
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub(crate) command_timeout: Option<Duration>,
+    /// Rewrites applied, in order, to links to the documentation and to the source code declaring each entry
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) url_rewrites: Vec<UrlRewrite>,
+    /// Headers sent with each request for `url` and `version_url`, e.g. to authenticate to a private site
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) headers: Vec<HttpHeader>,
+    /// The releases that can be picked between, substituted for the `{version}` placeholder in `url`, `version_url` and `command`. The first one is loaded by default.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) releases: Vec<String>,
+}
+
+impl SourceConfig {
+    pub(crate) const VERSION_PLACEHOLDER: &str = "{version}";
+
+    /// This source with the `{version}` placeholder replaced by `release`
+    pub(crate) fn for_release(&self, release: &str) -> Self {
+        let substitute = |s: &str| s.replace(Self::VERSION_PLACEHOLDER, release);
+        Self {
+            url: substitute(&self.url),
+            version_url: self.version_url.as_deref().map(substitute),
+            command: self.command.as_deref().map(substitute),
+            ..self.clone()
+        }
+    }
+
+    fn is_templated(&self) -> bool {
+        [
+            Some(&self.url),
+            self.version_url.as_ref(),
+            self.command.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|s| s.contains(Self::VERSION_PLACEHOLDER))
+    }
+}
+
+/// A regex replacement in the links of a source
+#[derive(Debug, Clone, Default, Encode, Decode, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct UrlRewrite {
+    /// The regular expression to replace every match of
+    pub(crate) pattern: String,
+    /// The replacement, in which e.g. `$1` or `${name}` refer to capture groups of `pattern`
+    pub(crate) replacement: String,
+}
+
+/// A request header of a source. Its value can be read from an environment variable or a file, to keep secrets out of the configuration file.
+#[derive(Clone, Default, Encode, Decode, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct HttpHeader {
+    pub(crate) name: String,
+    /// The value of the header, or the text before the value read from `value_env` or `value_file`, e.g. "Bearer ".
+    /// It may be a secret, so it is left out of caches.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[bitcode(skip)]
+    pub(crate) value: Option<String>,
+    /// An environment variable to read the value from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) value_env: Option<String>,
+    /// A file to read the value from, ignoring a trailing newline
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) value_file: Option<String>,
+}
+
+impl HttpHeader {
+    const REDACTED: &str = "<redacted>";
+
+    /// The name and full value of the header
+    pub(crate) fn resolve(&self) -> Result<(String, String)> {
+        let secret = match (&self.value_env, &self.value_file) {
+            (Some(var), _) => std::env::var(var).map_err(|err| {
+                eyre!(
+                    "Can't read the value of header {} from ${var}: {err}",
+                    self.name
+                )
+            })?,
+            (None, Some(file)) => std::fs::read_to_string(file)
+                .map_err(|err| {
+                    eyre!(
+                        "Can't read the value of header {} from {file}: {err}",
+                        self.name
+                    )
+                })?
+                .trim_end_matches(['\r', '\n'])
+                .to_string(),
+            (None, None) => String::new(),
+        };
+        let value = self.value.clone().unwrap_or_default() + &secret;
+        Ok((self.name.clone(), value))
+    }
+
+    fn validate(&self, source: &str) -> Result<()> {
+        ensure!(
+            !self.name.is_empty(),
+            "A header of source \"{source}\" has no name"
+        );
+        ensure!(
+            self.value_env.is_none() || self.value_file.is_none(),
+            "Header {} of source \"{source}\" can't have both value_env and value_file",
+            self.name
+        );
+        ensure!(
+            self.value.is_some() || self.value_env.is_some() || self.value_file.is_some(),
+            "Header {} of source \"{source}\" needs a value, value_env or value_file",
+            self.name
+        );
+        Ok(())
+    }
+}
+
+/// Literal values may be secrets, so they are never logged
+impl std::fmt::Debug for HttpHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("HttpHeader")
+            .field("name", &self.name)
+            .field("value", &self.value.as_ref().map(|_| Self::REDACTED))
+            .field("value_env", &self.value_env)
+            .field("value_file", &self.value_file)
+            .finish()
+    }
+}
+
+/// The formats of option data that nox knows how to parse
+#[derive(
+    Debug, Clone, Copy, Default, Encode, Decode, PartialEq, Eq, Deserialize, Serialize, ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum SourceFormat {
+    /// A rendered HTML manual with options in `<dt>`/`<dd>` pairs, like the NixOS manual
+    #[default]
+    Html,
+    /// The `options.json` file produced by the nixpkgs documentation build
+    OptionsJson,
+    /// The `packages.json` file describing the packages of a nixpkgs channel
+    PackagesJson,
+    /// The Nixpkgs reference manual, from which the `lib` function reference is parsed
+    NixpkgsLib,
+    /// The built-in functions page of the Nix manual
+    NixBuiltins,
+    /// A local `.nix` module file or a directory of them, from which `mkOption` declarations are extracted without evaluating Nix
+    NixModules,
+}
+
+impl SourceFormat {
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
 }
 
 impl Default for UserConfig {
@@ -48,9 +217,12 @@ impl Default for UserConfig {
             auto_refresh_cache: true,
             cache_duration: Duration::from_secs(7 * 24 * 60 * 60),
             cache_dir: project_paths::default_cache_dir().clone(),
+            system_cache_dirs: vec![],
             enable_logging: true,
             log_level: String::from("error"),
             log_file: project_paths::default_log_file().clone(),
+            use_flake_lock: true,
+            offline: false,
         }
     }
 }
@@ -63,7 +235,49 @@ impl UserConfig {
     }
 
     pub(super) fn build(config_file: &Path) -> Result<Self> {
-        Ok(Self::figment(config_file).extract()?)
+        let config: Self = Self::figment(config_file).extract()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Report mistakes that deserialization can't catch
+    fn validate(&self) -> Result<()> {
+        for source in &self.sources {
+            ensure!(
+                source.is_templated() || source.releases.is_empty(),
+                "Source \"{}\" has releases, but no {} placeholder to substitute them for",
+                source.name,
+                SourceConfig::VERSION_PLACEHOLDER
+            );
+            ensure!(
+                !source.is_templated() || !source.releases.is_empty(),
+                "Source \"{}\" has a {} placeholder, but no releases to substitute for it",
+                source.name,
+                SourceConfig::VERSION_PLACEHOLDER
+            );
+            for header in &source.headers {
+                header.validate(&source.name)?;
+            }
+            for rewrite in &source.url_rewrites {
+                Regex::new(&rewrite.pattern).map_err(|err| {
+                    eyre!(
+                        "Invalid url_rewrites pattern for source \"{}\": {err}",
+                        source.name
+                    )
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// This configuration with the literal values of headers replaced, so that it can be shown without leaking secrets
+    pub(crate) fn redacted(mut self) -> Self {
+        for header in self.sources.iter_mut().flat_map(|s| s.headers.iter_mut()) {
+            if header.value.is_some() {
+                header.value = Some(HttpHeader::REDACTED.to_string());
+            }
+        }
+        self
     }
 
     pub(crate) fn to_toml(&self) -> Result<String, toml::ser::Error> {
@@ -71,6 +285,7 @@ impl UserConfig {
     }
 }
 
+#[allow(clippy::too_many_lines)]
 pub(crate) fn default_config_toml() -> String {
     // File paths have to be computed at runtime because of differences between operating systems, if nothing else.
     let def = UserConfig::default();
@@ -85,12 +300,20 @@ auto_refresh_cache = true
 # The duration to keep cached results around for before automatically refreshing, if auto_refresh_cache = true.
 # Examples of valid duration specifications: "1week", "10days 2hours", "1d 2h 3m"
 # For all options see https://docs.rs/humantime/latest/humantime/fn.parse_duration.html
+# When refreshing, servers are asked whether the data changed since it was cached, so that unchanged
+# data is not downloaded again. This keeps a short duration cheap.
 cache_duration = "1week"
 
 # Directory in which to store cached results
 # Note that in TOML, strings in "double quotes" (basic strings) and in 'single quotes' (liteal strings) behave differently: certain characters like the backslash must be escaped in basic strings, but not in literal strings ('\').
 cache_dir = '{}'
 
+# Read-only cache directories that are checked after cache_dir, e.g. ["/var/cache/nox"] filled for
+# all users of a host by a NixOS module. Their caches are used as long as they are fresh, but
+# never written to: refreshed data is stored in cache_dir. Caches in the Nix store count as fresh,
+# since their age is unknown.
+system_cache_dirs = []
+
 # Whether to enable logging to file (mostly useful for debugging during development)
 enable_logging = true
 
@@ -101,6 +324,18 @@ log_level = "error"
 # Location of the log file, if used.
 log_file = '{}'
 
+# Whether to search the documentation of the releases that your flake is locked to, when nox is run
+# in a flake checkout. The `flake.lock` of the current directory or its closest parent is read,
+# and sources documenting the nixpkgs, home-manager or nix-darwin inputs are switched to the
+# release of the branch the input follows, e.g. "nixos-24.11" or "release-24.11". Sources with
+# `releases` pick the matching one, and tabs are marked with the input they follow.
+use_flake_lock = true
+
+# Whether to never touch the network, e.g. on a plane. Sources are then loaded from their cache
+# however old it is, tabs with outdated data are marked, and sources that were never cached fail
+# right away. Local files and directories are still read. Can also be set with `nox --offline`.
+offline = false
+
 ### Config sources ###
 # Each [[sources]] entry defines a separate config source and corresponding tab in nox.
 # The order of entries here determines the order the tabs are displayed in nox.
@@ -110,7 +345,81 @@ log_file = '{}'
 # but otherwise has no function.
 name = "Nix-Darwin"
 # The URL from which to get the data for this entry.
+# This can also be a `file://` URL or a path on the local filesystem, e.g. to a manual you built
+# yourself like "result/share/doc/nixos". Local sources are re-read whenever the files change,
+# instead of after `cache_duration`.
 url = "https://nix-darwin.github.io/nix-darwin/manual/index.html"
+# The format of the data at `url`. One of:
+# - "html" (the default): A rendered options manual, like the NixOS manual.
+# - "options-json": The `options.json` file produced when building the documentation of
+#   nixpkgs (or any other module system project), e.g. from a local path like
+#   "result/share/doc/nixos/options.json".
+# - "packages-json": The `packages.json` file listing the packages in a nixpkgs channel, or the
+#   output of `nix-env -qa --json --meta`. The tab will show packages instead of options.
+#   Downloads are limited to 30 MB, and the file published by a channel is larger than that once
+#   decompressed, so read it from a local path instead, e.g. after running
+#   `curl -L https://channels.nixos.org/nixos-unstable/packages.json.br | brotli -d > packages.json`.
+#   Package definitions in nixpkgs link to the branch of the channel if `url` is on
+#   channels.nixos.org, and to master otherwise.
+# - "nixpkgs-lib": The Nixpkgs reference manual. The tab will list the functions in `lib`
+#   with their type signatures, descriptions and examples. For example:
+#
+#   [[sources]]
+#   name = "Nixpkgs lib"
+#   url = "https://nixos.org/manual/nixpkgs/stable/"
+#   format = "nixpkgs-lib"
+# - "nix-builtins": The page of the Nix manual documenting the built-in functions.
+# - "nix-modules": A local `.nix` module file, or a directory that is searched for them, e.g. the
+#   modules of your own flake. The options are read from the `mkOption`, `mkEnableOption` and
+#   `mkPackageOption` declarations without evaluating Nix, so options declared by computed names
+#   are missed. For example:
+#
+#   [[sources]]
+#   name = "My modules"
+#   url = "/etc/nixos/modules"
+#   format = "nix-modules"
+# format = "html"
+#
+# Instead of reading `url`, the data of a source can be produced by a shell command, e.g. to get the
+# options of a private flake. The output of the command is parsed according to `format`, and `url`
+# is then only used for links to the documentation. The command is run in the directory nox was
+# started in, and is given up on after `command_timeout` (by default "5m"). For example:
+#
+#   [[sources]]
+#   name = "My flake"
+#   url = "https://git.example.com/me/my-flake"
+#   format = "options-json"
+#   command = "cat $(nix build .#optionsJson --no-link --print-out-paths)/share/doc/nixos/options.json"
+#   command_timeout = "10m"
+#
+# Links to the documentation and to the source code of each entry can be changed with `url_rewrites`,
+# e.g. to point to a fork or mirror of nixpkgs. Every match of the regex `pattern` is replaced
+# with `replacement`, in which `$1` or `${{name}}` refer to capture groups. Rules are applied in order:
+#
+#   [[sources.url_rewrites]]
+#   pattern = '^https://github\.com/NixOS/nixpkgs/blob/[^/]+/'
+#   replacement = "https://git.example.com/mirrors/nixpkgs/-/blob/main/"
+#
+# Requests for `url` and `version_url` can carry extra headers, e.g. to authenticate to a private
+# documentation site. To keep secrets out of this file, a value can be read from an environment
+# variable (`value_env`) or a file (`value_file`), in which case `value` is put before it.
+# `nox print-config` redacts literal values:
+#
+#   [[sources.headers]]
+#   name = "Authorization"
+#   value = "Bearer "
+#   value_env = "DOCS_TOKEN"
+#
+# To switch between several releases of a source, e.g. the NixOS release running on each of your
+# hosts, put a `{{version}}` placeholder in `url`, `version_url` or `command`, and list the releases
+# to substitute for it. The first release is loaded at startup, and <C-r> picks another one. Each
+# release is cached separately, and the version shown in the tab confirms which one was loaded:
+#
+#   [[sources]]
+#   name = "NixOS"
+#   url = "https://nixos.org/manual/nixos/{{version}}/options"
+#   version_url = "https://nixos.org/manual/nixos/{{version}}/"
+#   releases = ["24.11", "24.05"]
 
 [[sources]]
 name = "NixOS"
@@ -121,17 +430,16 @@ url = "https://nixos.org/manual/nixos/stable/options"
 version_url = "https://nixos.org/manual/nixos/stable/"
 
 [[sources]]
-# The "NixOS Unstable" name currently triggers special behaviour to fix links to the source
-# of each configuration option.
-# Namely, leading up to each new NixOS stable release, the Unstable documentation switches to
-# linking to the new stable branch of the github.com/nixos/nixpkgs repo before that stable branch
-# has been created, resulting in HTTP 404 errors.
-# As a special case, for a source with the name "NixOS Unstable", if a substring of the form "release-\d{{2}}\.\d{{2}}"
-# (e.g. "release-25.11") is detected in links to the source code defining a given option,
-# it is replaced with "nixos-unstable".
 name = "NixOS Unstable"
 url = "https://nixos.org/manual/nixos/unstable/options"
 version_url = "https://nixos.org/manual/nixos/unstable/"
+# Leading up to each new NixOS stable release, the Unstable documentation switches to linking to
+# the new stable branch of the github.com/nixos/nixpkgs repo before that stable branch has been
+# created, resulting in HTTP 404 errors. This replaces branches like "release-25.11" in links to the
+# source code of each option with "nixos-unstable".
+[[sources.url_rewrites]]
+pattern = 'release-\d{{2}}\.\d{{2}}'
+replacement = "nixos-unstable"
 
 [[sources]]
 name = "Home Manager"
@@ -151,6 +459,7 @@ version_url = "https://nix-community.github.io/home-manager/"
 [[sources]]
 name = "Nix Built-ins"
 url = "https://nix.dev/manual/nix/2.28/language/builtins.html"
+format = "nix-builtins"
 "#,
         def.cache_dir.display(),
         def.log_file.display()
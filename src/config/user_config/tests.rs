@@ -27,6 +27,15 @@ fn documented_default_config_is_correct() -> Result<()> {
         );
         assert_eq!(internal_defaults.log_level, documented_defaults.log_level);
         assert_eq!(internal_defaults.log_file, documented_defaults.log_file);
+        assert_eq!(
+            internal_defaults.use_flake_lock,
+            documented_defaults.use_flake_lock
+        );
+        assert_eq!(internal_defaults.offline, documented_defaults.offline);
+        assert_eq!(
+            internal_defaults.system_cache_dirs,
+            documented_defaults.system_cache_dirs
+        );
         if internal_defaults.sources != documented_defaults.sources {
             eprintln!("internal_defaults.sources:");
             eprintln!("{:#?}", internal_defaults.sources);
@@ -48,9 +57,12 @@ fn roundtrip_linux_paths() -> Result<()> {
         auto_refresh_cache: true,
         cache_duration: Duration::from_secs(7 * 24 * 60 * 60),
         cache_dir: PathBuf::from_str("/home/runneradmin/.cache/nox")?,
+        system_cache_dirs: vec![PathBuf::from_str("/var/cache/nox")?],
         enable_logging: true,
         log_level: String::from("error"),
         log_file: PathBuf::from_str("logs/nox.log")?,
+        use_flake_lock: true,
+        offline: false,
     };
 
     let toml = conf.to_toml()?;
@@ -70,9 +82,12 @@ use_cache = true
 auto_refresh_cache = false
 cache_duration = "2months"
 cache_dir = '/home/me/.ca\che/nox'
+system_cache_dirs = ["/var/cache/nox", "/nix/store/0000000000000000000000000000000-nox-cache"]
 enable_logging = true
 log_level = "warn,nix-options-search=info"
 log_file = "äéáßfð·\\comp/.log"
+use_flake_lock = false
+offline = true
 
 [[sources]]
 name = "Some random name"
@@ -92,6 +107,15 @@ url = "not even an url"
     assert!(conf.enable_logging);
     assert_eq!(conf.log_level, String::from("warn,nix-options-search=info"));
     assert_eq!(conf.log_file, PathBuf::from_iter([r"äéáßfð·\comp", ".log"]));
+    assert!(!conf.use_flake_lock);
+    assert!(conf.offline);
+    assert_eq!(
+        conf.system_cache_dirs,
+        [
+            PathBuf::from("/var/cache/nox"),
+            PathBuf::from("/nix/store/0000000000000000000000000000000-nox-cache")
+        ]
+    );
     Ok(())
 }
 
@@ -104,9 +128,12 @@ fn roundtrip_windows_paths() -> Result<()> {
         auto_refresh_cache: true,
         cache_duration: Duration::from_secs(7 * 24 * 60 * 60),
         cache_dir: PathBuf::from_str(r"C:\Users\runneradmin\AppData\Local\mvil\nox\cache")?,
+        system_cache_dirs: vec![],
         enable_logging: true,
         log_level: String::from("error"),
         log_file: PathBuf::from_str(r"logs\nox.log")?,
+        use_flake_lock: true,
+        offline: false,
     };
     let toml = conf.to_toml()?;
     // Escaping necessary so '' is used
@@ -125,9 +152,12 @@ fn roundtrip_mixed_paths_windows() -> Result<()> {
         auto_refresh_cache: true,
         cache_duration: Duration::from_secs(7 * 24 * 60 * 60),
         cache_dir: PathBuf::from_str(r"C:\home/runneradmin/.cache/nox")?,
+        system_cache_dirs: vec![],
         enable_logging: true,
         log_level: String::from("error"),
         log_file: PathBuf::from_str("logs/nox.log")?,
+        use_flake_lock: true,
+        offline: false,
     };
     let toml = conf.to_toml()?;
     // `/` should also be a path separator on Windows
@@ -147,9 +177,12 @@ auto_refresh_cache = false
 cache_duration = "2months"
 # Deliberately test escaped basic strings (and comments)
 cache_dir = "C:\\Users\\me\\cache"
+system_cache_dirs = []
 enable_logging = true
 log_level = "warn,nix-options-search=info"
 log_file = 'äéáßfð·\.log'
+use_flake_lock = true
+offline = false
 
 [[sources]]
 name = "Some random name"
@@ -171,3 +204,89 @@ url = "not even an url"
     assert_eq!(conf.log_file, PathBuf::from_iter(["äéáßfð·", ".log"]));
     Ok(())
 }
+
+#[test]
+fn invalid_url_rewrite_is_rejected() -> Result<()> {
+    let tmpdir = tempfile::tempdir()?;
+    let config_file = tmpdir.path().join("config.toml");
+    std::fs::write(
+        &config_file,
+        r#"
+[[sources]]
+name = "Mirror"
+url = "https://example.com/options.html"
+url_rewrites = [{ pattern = "release-(", replacement = "main" }]
+"#,
+    )?;
+    let err = UserConfig::build(&config_file)
+        .expect_err("Unbalanced parenthesis is rejected")
+        .to_string();
+    assert!(err.contains("Mirror"), "{err}");
+    Ok(())
+}
+
+#[test]
+fn releases_need_a_version_placeholder() -> Result<()> {
+    let tmpdir = tempfile::tempdir()?;
+    let config_file = tmpdir.path().join("config.toml");
+    std::fs::write(
+        &config_file,
+        r#"
+[[sources]]
+name = "NixOS"
+url = "https://nixos.org/manual/nixos/stable/options"
+releases = ["24.05", "24.11"]
+"#,
+    )?;
+    let err = UserConfig::build(&config_file)
+        .expect_err("Releases without a placeholder are rejected")
+        .to_string();
+    assert!(err.contains("NixOS"), "{err}");
+
+    std::fs::write(
+        &config_file,
+        r#"
+[[sources]]
+name = "NixOS"
+url = "https://nixos.org/manual/nixos/{version}/options"
+version_url = "https://nixos.org/manual/nixos/{version}/"
+releases = ["24.05", "24.11"]
+"#,
+    )?;
+    let config = UserConfig::build(&config_file)?;
+    let release = config.sources[0].for_release("24.11");
+    assert_eq!(release.url, "https://nixos.org/manual/nixos/24.11/options");
+    assert_eq!(
+        release.version_url.as_deref(),
+        Some("https://nixos.org/manual/nixos/24.11/")
+    );
+    Ok(())
+}
+
+#[test]
+fn header_values_are_redacted() -> Result<()> {
+    let conf = UserConfig {
+        sources: vec![SourceConfig {
+            name: "Private".to_string(),
+            url: "https://docs.example.com/options.html".to_string(),
+            headers: vec![
+                HttpHeader {
+                    name: "Cookie".to_string(),
+                    value: Some("session=s3cret".to_string()),
+                    ..Default::default()
+                },
+                HttpHeader {
+                    name: "Authorization".to_string(),
+                    value_env: Some("DOCS_TOKEN".to_string()),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }],
+        ..UserConfig::default()
+    };
+    let toml = conf.redacted().to_toml()?;
+    assert!(!toml.contains("s3cret"), "{toml}");
+    assert!(toml.contains("DOCS_TOKEN"), "{toml}");
+    Ok(())
+}
@@ -0,0 +1,310 @@
+use crate::cache::Cacheable;
+use crate::cli::OutputFormat;
+use crate::config::{AppConfig, SourceConfig, SourceFormat};
+use crate::parsing::OptText;
+use crate::source::{Source, SourceData};
+use color_eyre::eyre::{ensure, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use tracing::debug;
+
+/// Which options were added, removed or changed between two versions of a source.
+/// Options are matched by their id, and are listed by name in alphabetical order.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub(crate) struct OptionsDiff {
+    pub(crate) added: Vec<String>,
+    pub(crate) removed: Vec<String>,
+    pub(crate) changed: Vec<ChangedOption>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct ChangedOption {
+    pub(crate) name: String,
+    pub(crate) changes: Vec<FieldChange>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct FieldChange {
+    pub(crate) field: &'static str,
+    pub(crate) old: String,
+    pub(crate) new: String,
+}
+
+impl OptionsDiff {
+    pub(crate) fn new(old: &[OptText], new: &[OptText]) -> Self {
+        let by_id = |opts: &[OptText]| -> BTreeMap<String, OptText> {
+            opts.iter().map(|o| (o.id.clone(), o.clone())).collect()
+        };
+        let (old, new) = (by_id(old), by_id(new));
+
+        let mut diff = Self::default();
+        for (id, new_opt) in &new {
+            let Some(old_opt) = old.get(id) else {
+                diff.added.push(new_opt.name.clone());
+                continue;
+            };
+            let changes = [
+                ("type", &old_opt.var_type, &new_opt.var_type),
+                ("default", &old_opt.default, &new_opt.default),
+            ]
+            .into_iter()
+            .filter(|(_, old, new)| old != new)
+            .map(|(field, old, new)| FieldChange {
+                field,
+                old: old.clone(),
+                new: new.clone(),
+            })
+            .collect::<Vec<_>>();
+            if !changes.is_empty() {
+                diff.changed.push(ChangedOption {
+                    name: new_opt.name.clone(),
+                    changes,
+                });
+            }
+        }
+        diff.removed = old
+            .iter()
+            .filter(|(id, _)| !new.contains_key(*id))
+            .map(|(_, opt)| opt.name.clone())
+            .collect();
+
+        diff.added.sort();
+        diff.removed.sort();
+        diff.changed.sort_by(|a, b| a.name.cmp(&b.name));
+        diff
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// The version of a source that is compared
+#[derive(Debug, Serialize)]
+struct Snapshot {
+    source: String,
+    url: String,
+    version: String,
+}
+
+impl From<&SourceData> for Snapshot {
+    fn from(data: &SourceData) -> Self {
+        Self {
+            source: data.source.to_string(),
+            url: data.source.url().to_string(),
+            version: data.version.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DiffReport {
+    old: Snapshot,
+    new: Snapshot,
+    #[serde(flatten)]
+    diff: OptionsDiff,
+}
+
+impl DiffReport {
+    fn to_text(&self) -> String {
+        let mut out = String::new();
+        let describe = |s: &Snapshot| format!("{} ({})", s.source, s.version);
+        // Writing to a String can't fail
+        let _ = writeln!(
+            out,
+            "Comparing {} with {}",
+            describe(&self.old),
+            describe(&self.new)
+        );
+        if self.diff.is_empty() {
+            let _ = writeln!(out, "\nNo options were added, removed or changed.");
+            return out;
+        }
+        let sections = [
+            ("Added", "+", &self.diff.added),
+            ("Removed", "-", &self.diff.removed),
+        ];
+        for (title, marker, names) in sections {
+            if !names.is_empty() {
+                let _ = writeln!(out, "\n{title} ({}):", names.len());
+                for name in names {
+                    let _ = writeln!(out, "  {marker} {name}");
+                }
+            }
+        }
+        if !self.diff.changed.is_empty() {
+            let _ = writeln!(out, "\nChanged ({}):", self.diff.changed.len());
+            for opt in &self.diff.changed {
+                let _ = writeln!(out, "  ~ {}", opt.name);
+                for change in &opt.changes {
+                    let _ = writeln!(
+                        out,
+                        "      {}: {} -> {}",
+                        change.field,
+                        one_line(&change.old),
+                        one_line(&change.new)
+                    );
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Multi-line defaults are shown on a single line, so each change stays on its line
+fn one_line(s: &str) -> String {
+    let s = s.split_whitespace().collect::<Vec<_>>().join(" ");
+    if s.is_empty() {
+        "(none)".to_string()
+    } else {
+        s
+    }
+}
+
+/// Print the differences between the options of `old` and `new`, which are specified as described for `nox diff`
+pub(crate) fn print_diff(
+    config: &AppConfig,
+    old: &str,
+    new: &str,
+    fresh: bool,
+    format: SourceFormat,
+    output: OutputFormat,
+) -> Result<()> {
+    let old = load_snapshot(config, old, format, false)?;
+    let new = load_snapshot(config, new, format, fresh)?;
+    ensure!(
+        !old.opts.is_empty() || !new.opts.is_empty(),
+        "Neither {} nor {} has any options to compare",
+        old.source,
+        new.source
+    );
+    let report = DiffReport {
+        old: Snapshot::from(&old),
+        new: Snapshot::from(&new),
+        diff: OptionsDiff::new(&old.opts, &new.opts),
+    };
+    match output {
+        OutputFormat::Text => print!("{}", report.to_text()),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    Ok(())
+}
+
+/// The data of a configured source (optionally `name@release`), or of a URL or path in the given format.
+/// Configured sources are read from their cache as it is, however old, unless `fresh` is set.
+fn load_snapshot(
+    config: &AppConfig,
+    spec: &str,
+    format: SourceFormat,
+    fresh: bool,
+) -> Result<SourceData> {
+    let Some(source) = configured_source(config, spec)? else {
+        ensure!(
+            spec.contains("://") || std::path::Path::new(spec).exists(),
+            "{spec} is neither the name of a configured source ({}) nor a URL or existing path",
+            config
+                .sources
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let source = Source::from(&SourceConfig {
+            name: spec.to_string(),
+            url: spec.to_string(),
+            format,
+            ..Default::default()
+        });
+        ensure_online(config, &source)?;
+        return source.get_online_data();
+    };
+
+    let cache = source.cache_config(config);
+    if !fresh {
+        if let Some(file) = cache.file.as_ref().filter(|f| f.exists()) {
+            match Source::load_cache(file) {
+                Ok(data) if data.source == source.redacted() => return Ok(data),
+                res => debug!(?file, ok = res.is_ok(), "Cache can't be compared"),
+            }
+        }
+        return source.get_data(&cache);
+    }
+    ensure_online(config, &source)?;
+    source.get_online_data()
+}
+
+fn ensure_online(config: &AppConfig, source: &Source) -> Result<()> {
+    ensure!(
+        !config.offline || !source.needs_network(),
+        "{source} can't be downloaded in offline mode"
+    );
+    Ok(())
+}
+
+fn configured_source(config: &AppConfig, spec: &str) -> Result<Option<Source>> {
+    let find = |name: &str| {
+        config
+            .sources
+            .iter()
+            .find(|s| s.name.eq_ignore_ascii_case(name))
+    };
+    if let Some(source) = find(spec) {
+        return Ok(Some(Source::from(source)));
+    }
+    let Some((source, release)) = spec
+        .rsplit_once('@')
+        .and_then(|(name, release)| Some((find(name)?, release)))
+    else {
+        return Ok(None);
+    };
+    ensure!(
+        source.releases.iter().any(|r| r == release),
+        "{} has no release {release}, only: {}",
+        source.name,
+        source.releases.join(", ")
+    );
+    Ok(Some(Source::with_release(source, Some(release))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::parse_options_json;
+
+    #[test]
+    fn diff_options() -> Result<()> {
+        let old = parse_options_json(
+            r#"{
+"services.foo.enable": {"type": "boolean", "default": false},
+"services.foo.port": {"type": "16 bit unsigned integer; between 0 and 65535 (both inclusive)", "default": 80},
+"services.bar.enable": {"type": "boolean", "default": false}
+}"#,
+        )?;
+        let new = parse_options_json(
+            r#"{
+"services.foo.enable": {"type": "boolean", "default": false},
+"services.foo.port": {"type": "signed integer", "default": 8080},
+"services.baz.enable": {"type": "boolean", "default": false}
+}"#,
+        )?;
+        let diff = OptionsDiff::new(&old, &new);
+        assert_eq!(diff.added, vec!["services.baz.enable"]);
+        assert_eq!(diff.removed, vec!["services.bar.enable"]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].name, "services.foo.port");
+        assert_eq!(
+            diff.changed[0]
+                .changes
+                .iter()
+                .map(|c| (c.field, c.new.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("type", "signed integer"), ("default", "8080")]
+        );
+        assert!(OptionsDiff::new(&new, &new).is_empty());
+
+        let json = serde_json::to_value(&diff)?;
+        assert_eq!(json["changed"][0]["changes"][1]["old"], "80");
+        Ok(())
+    }
+}
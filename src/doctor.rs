@@ -0,0 +1,397 @@
+use crate::cache::{CacheStatus, CacheValidity, Cacheable};
+use crate::config::{AppConfig, SourceConfig, SourceFormat};
+use crate::source::{Source, SourceData, SourceVersion, UrlRewriter};
+use color_eyre::eyre::{bail, ensure, Result};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// How a single check of a source turned out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Level {
+    Ok,
+    /// Something that degrades the tab, but doesn't leave it empty
+    Warn,
+    Fail,
+}
+
+#[derive(Debug)]
+struct Check {
+    level: Level,
+    name: &'static str,
+    message: String,
+}
+
+/// The checks of one source, in the order they were made
+#[derive(Debug)]
+struct SourceReport {
+    source: String,
+    url: String,
+    checks: Vec<Check>,
+}
+
+impl SourceReport {
+    fn push(&mut self, level: Level, name: &'static str, message: impl Into<String>) {
+        self.checks.push(Check {
+            level,
+            name,
+            message: message.into(),
+        });
+    }
+
+    fn level(&self) -> Level {
+        self.checks
+            .iter()
+            .map(|c| c.level)
+            .max()
+            .unwrap_or(Level::Ok)
+    }
+}
+
+impl fmt::Display for SourceReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} ({})", self.source, self.url)?;
+        for check in &self.checks {
+            let marker = match check.level {
+                Level::Ok => "[ok]  ",
+                Level::Warn => "[warn]",
+                Level::Fail => "[FAIL]",
+            };
+            writeln!(f, "  {marker} {}: {}", check.name, check.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check every configured source (or those named in `names`) and print a report.
+/// Returns an error if any source would fail to load, so that the exit code shows it.
+pub(crate) fn run_doctor(config: &AppConfig, names: &[String]) -> Result<()> {
+    ensure!(
+        !config.offline,
+        "nox doctor downloads each source, which offline mode doesn't allow"
+    );
+    let configs = config
+        .sources
+        .iter()
+        .filter(|s| names.is_empty() || names.iter().any(|n| n.eq_ignore_ascii_case(&s.name)))
+        .collect::<Vec<_>>();
+    if configs.is_empty() {
+        bail!("No configured source is named {}", names.join(" or "));
+    }
+
+    let mut failed = 0;
+    let mut total = 0;
+    for source_config in configs {
+        for source in Source::each_release(source_config) {
+            let report = check_source(&source, config);
+            println!("{report}");
+            total += 1;
+            if report.level() == Level::Fail {
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{failed} of {total} sources failed");
+    }
+    println!("All {total} sources are fine.");
+    Ok(())
+}
+
+fn check_source(source: &Source, config: &AppConfig) -> SourceReport {
+    let mut report = SourceReport {
+        source: source.to_string(),
+        url: source.url().to_string(),
+        checks: vec![],
+    };
+    // Parse without rewriting links, so the rewrites can be checked separately
+    let unrewritten = Source {
+        inner: SourceConfig {
+            url_rewrites: vec![],
+            ..source.inner.clone()
+        },
+        release: source.release.clone(),
+    };
+    if let Some((data, version)) = check_data(&unrewritten, &mut report) {
+        check_entries(&data, &mut report);
+        check_version(&version, &mut report);
+        check_links(source, &data, &mut report);
+    }
+    check_cache(source, config, &mut report);
+    report
+}
+
+/// Fetch and parse the data of `source`, and find its version
+fn check_data(source: &Source, report: &mut SourceReport) -> Option<(SourceData, SourceVersion)> {
+    let start = Instant::now();
+    if source.inner.format == SourceFormat::NixModules && source.inner.command.is_none() {
+        return match source.get_online_data() {
+            Ok(data) => {
+                report.push(
+                    Level::Ok,
+                    "data",
+                    format!("read local modules in {:.1?}", start.elapsed()),
+                );
+                let version = SourceVersion::Found(data.version.clone());
+                Some((data, version))
+            }
+            Err(err) => {
+                report.push(Level::Fail, "data", format!("{err}"));
+                None
+            }
+        };
+    }
+
+    let data_html = match source.get_data_html() {
+        Ok(page) => {
+            let origin = match page.status {
+                Some(status) => format!("downloaded (HTTP {status})"),
+                None if source.inner.command.is_some() => "command output".to_string(),
+                None => "read local file".to_string(),
+            };
+            report.push(
+                Level::Ok,
+                "data",
+                format!(
+                    "{origin}, {} in {:.1?}",
+                    format_size(page.body.len()),
+                    start.elapsed()
+                ),
+            );
+            page.body
+        }
+        Err(err) => {
+            report.push(Level::Fail, "data", format!("{err}"));
+            return None;
+        }
+    };
+    let version_html = if source.url() == source.version_url() || source.inner.command.is_some() {
+        data_html.clone()
+    } else {
+        match source.get_version_html() {
+            Ok(page) => page.body,
+            Err(err) => {
+                report.push(
+                    Level::Warn,
+                    "version page",
+                    format!("{}: {err}", source.version_url()),
+                );
+                String::new()
+            }
+        }
+    };
+    match source.parse_data(&data_html, &version_html) {
+        Ok(data) => Some((data, source.parse_version(&version_html))),
+        Err(err) => {
+            report.push(Level::Fail, "parse", format!("{err}"));
+            None
+        }
+    }
+}
+
+fn check_entries(data: &SourceData, report: &mut SourceReport) {
+    let counts = [
+        (data.opts.len(), "options"),
+        (data.packages.len(), "packages"),
+        (data.functions.len(), "functions"),
+    ]
+    .into_iter()
+    .filter(|(n, _)| *n > 0)
+    .map(|(n, what)| format!("{n} {what}"))
+    .collect::<Vec<_>>();
+    if counts.is_empty() {
+        report.push(Level::Fail, "parse", "found no entries");
+    } else {
+        report.push(Level::Ok, "parse", counts.join(", "));
+    }
+    let skipped = &data.parse_report.skipped;
+    if !skipped.is_empty() {
+        let examples = skipped
+            .iter()
+            .take(3)
+            .map(|s| format!("{} ({})", s.label, s.reason))
+            .collect::<Vec<_>>()
+            .join(", ");
+        report.push(
+            Level::Warn,
+            "skipped",
+            format!("{} malformed entries, e.g. {examples}", skipped.len()),
+        );
+    }
+}
+
+fn check_version(version: &SourceVersion, report: &mut SourceReport) {
+    let (level, message) = match version {
+        SourceVersion::Found(_) => (Level::Ok, version.to_string()),
+        SourceVersion::Error(err) => (Level::Warn, format!("{version}: {err}")),
+        SourceVersion::Mismatch { .. } | SourceVersion::NotFound => {
+            (Level::Warn, version.to_string())
+        }
+    };
+    report.push(level, "version", message);
+}
+
+/// Apply the `url_rewrites` of `source` to the declared-by links, and check that a rewritten link is reachable
+fn check_links(source: &Source, data: &SourceData, report: &mut SourceReport) {
+    let links = data
+        .opts
+        .iter()
+        .flat_map(|o| o.declared_by_urls.iter())
+        .chain(
+            data.functions
+                .iter()
+                .flat_map(|f| f.declared_by_urls.iter()),
+        )
+        .chain(data.packages.iter().filter_map(|p| p.position_url.as_ref()))
+        .collect::<Vec<_>>();
+    let Some(first) = links.first() else {
+        return;
+    };
+    let rewriter = UrlRewriter::new(&source.inner.url_rewrites);
+    let rewritten = links
+        .iter()
+        .filter(|l| rewriter.rewrite(l) != ***l)
+        .collect::<Vec<_>>();
+    let sample = rewritten.first().unwrap_or(&first);
+    let sample_rewritten = rewriter.rewrite(sample);
+
+    let mut message = format!("{} declared-by links", links.len());
+    if !source.inner.url_rewrites.is_empty() {
+        if rewritten.is_empty() {
+            report.push(
+                Level::Warn,
+                "links",
+                format!("{message}, none changed by url_rewrites"),
+            );
+            return;
+        }
+        message = format!(
+            "{message}, {} changed by url_rewrites, e.g. {sample} -> {sample_rewritten}",
+            rewritten.len()
+        );
+    }
+    if !sample_rewritten.starts_with("http") {
+        report.push(Level::Ok, "links", message);
+        return;
+    }
+    match link_status(&sample_rewritten) {
+        Ok(()) => report.push(Level::Ok, "links", message),
+        Err(err) => report.push(
+            Level::Warn,
+            "links",
+            format!("{message}; {sample_rewritten} is unreachable: {err}"),
+        ),
+    }
+}
+
+fn link_status(url: &str) -> Result<()> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(10)))
+        .build()
+        .into();
+    agent.head(url).call()?;
+    Ok(())
+}
+
+fn check_cache(source: &Source, config: &AppConfig, report: &mut SourceReport) {
+    let cache = source.cache_config(config);
+    let status = match source.cache_status(&cache) {
+        Ok(status) => status,
+        Err(err) => {
+            report.push(Level::Fail, "cache", format!("{err}"));
+            return;
+        }
+    };
+    let Some(file) = &cache.file else {
+        report.push(Level::Ok, "cache", "caching is disabled");
+        return;
+    };
+    let freshness = match status {
+        CacheStatus::Undefined => return report.push(Level::Ok, "cache", "caching is disabled"),
+        CacheStatus::Missing => {
+            return report.push(
+                Level::Ok,
+                "cache",
+                format!("{} does not exist yet", file.display()),
+            )
+        }
+        CacheStatus::Fresh => "fresh",
+        CacheStatus::Outdated => "outdated",
+    };
+    let age = std::fs::metadata(file)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|m| m.elapsed().ok())
+        .map_or_else(
+            || "unknown age".to_string(),
+            |age| format!("{} old", format_age(age)),
+        );
+    let (level, validity) = match Source::load_cache(file) {
+        Ok(data) => match source.cache_valid(&data) {
+            CacheValidity::Good => (Level::Ok, "valid".to_string()),
+            CacheValidity::Fallback => (
+                Level::Warn,
+                "from an older configuration, only used if fresh data can't be loaded".to_string(),
+            ),
+            CacheValidity::Unusable => (Level::Warn, "unusable, will be replaced".to_string()),
+        },
+        Err(err) => (Level::Warn, format!("unreadable ({err}), will be replaced")),
+    };
+    report.push(
+        level,
+        "cache",
+        format!("{freshness}, {age}, {validity}: {}", file.display()),
+    );
+}
+
+#[allow(clippy::cast_precision_loss)]
+pub(crate) fn format_size(bytes: usize) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+        b if b >= 1024 => format!("{:.1} kB", b as f64 / 1024.0),
+        b => format!("{b} bytes"),
+    }
+}
+
+pub(crate) fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        s if s >= 24 * 60 * 60 => format!("{} days", s / (24 * 60 * 60)),
+        s if s >= 60 * 60 => format!("{} hours", s / (60 * 60)),
+        s => format!("{} minutes", s / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::local_options_source;
+    use tempfile::tempdir;
+
+    #[test]
+    fn doctor_reports_sources() {
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        let config = AppConfig {
+            sources: vec![],
+            cache_dir: None,
+            ..AppConfig::default()
+        };
+
+        let good = Source::from(&local_options_source(tmpdir.path()));
+        let report = check_source(&good, &config);
+        assert_eq!(report.level(), Level::Warn, "{report}");
+        assert!(
+            report.to_string().contains("[ok]   parse: 1 options"),
+            "{report}"
+        );
+
+        let missing = Source::from(&SourceConfig {
+            name: "Missing".to_string(),
+            url: tmpdir.path().join("missing.json").display().to_string(),
+            format: SourceFormat::OptionsJson,
+            ..Default::default()
+        });
+        let report = check_source(&missing, &config);
+        assert_eq!(report.level(), Level::Fail, "{report}");
+        assert!(report.to_string().contains("[FAIL] data"), "{report}");
+    }
+}
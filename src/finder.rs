@@ -1,14 +1,12 @@
-use crate::cache::{CacheConfig, Cacheable};
-use crate::parsing::OptText;
+use crate::cache::{CacheConfig, Cacheable, Offline};
+use crate::parsing::{Entry, ParseReport};
 use crate::source::{Source, SourceData};
 use color_eyre::eyre::Result;
 use nucleo::pattern::{CaseMatching, Normalization};
-use nucleo::{Config, Nucleo};
-use std::path::Path;
+use nucleo::{Config, Injector, Nucleo};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, OnceLock};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::thread::JoinHandle;
-use std::time::Duration;
 use tracing::debug;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -20,40 +18,44 @@ pub(crate) enum InputStatus {
 
 pub(crate) struct Finder {
     source: Source,
+    /// The `flake.lock` input the source was pinned to
+    lock_input: Option<String>,
     version: Arc<OnceLock<String>>,
-    searcher: Nucleo<OptText>,
+    parse_report: Arc<OnceLock<ParseReport>>,
+    /// A searcher for fresh data that arrived while outdated cached data was searched, waiting to replace it
+    fresh_data: Arc<Mutex<Option<FreshSearcher>>>,
+    /// The fresh searcher taken from `fresh_data`, which replaces `searcher` once it matched the pattern
+    replacement: Option<FreshSearcher>,
+    /// Whether outdated cached data is searched while fresh data is fetched
+    refreshing: Arc<AtomicBool>,
+    /// Whether the searched data is outdated, and stays so unless it is refreshed
+    stale: Arc<AtomicBool>,
+    searcher: Nucleo<Entry>,
     #[cfg(test)]
     injection_handle: Option<JoinHandle<()>>,
     pub(crate) results_waiting: Arc<AtomicBool>,
 }
 
 impl Finder {
-    pub(crate) fn new(
-        source: Source,
-        cache_dir: Option<&'static Path>,
-        cache_duration: Option<Duration>,
-    ) -> Self {
-        Self::new_with_data_fn(source, None, cache_dir, cache_duration)
+    pub(crate) fn new(source: Source, cache: CacheConfig) -> Self {
+        Self::new_with_data_fn(source, None, cache)
     }
 
     // Allows for overriding the data source, namely for tests that specifically want to acquire data online or from cache.
     pub(crate) fn new_with_data_fn(
         source: Source,
         data_fn: Option<Box<dyn FnOnce() -> Result<SourceData> + Send>>,
-        cache_dir: Option<&'static Path>,
-        cache_duration: Option<Duration>,
+        cache: CacheConfig,
     ) -> Self {
-        let source_clone = source.clone();
-        let data_fn = data_fn.unwrap_or(Box::new(move || {
-            let res = source_clone.get_data(&CacheConfig {
-                file: cache_dir.map(|p| p.join(format!("{source_clone}.zst"))),
-                duration: cache_duration,
-            });
-            if res.is_err() {
-                debug!(?res);
-            }
-            res
-        }));
+        let load: LoadFn = if let Some(data_fn) = data_fn {
+            Box::new(move |show| {
+                show(data_fn()?, false);
+                Ok(())
+            })
+        } else {
+            let source = source.clone();
+            Box::new(move |show| source.get_data_stale_while_revalidate(&cache, show))
+        };
 
         let results_waiting = Arc::new(AtomicBool::new(false));
         let results_sender = Arc::clone(&results_waiting);
@@ -61,10 +63,30 @@ impl Finder {
             results_sender.store(true, Ordering::Relaxed);
         });
         let version = Arc::new(OnceLock::new());
-        let (searcher, _handle) = new_searcher(data_fn, version.clone(), notify);
+        let parse_report = Arc::new(OnceLock::new());
+        let fresh_data = Arc::new(Mutex::new(None));
+        let refreshing = Arc::new(AtomicBool::new(false));
+        let stale = Arc::new(AtomicBool::new(false));
+        let (searcher, _handle) = new_searcher(
+            load,
+            Loaded {
+                version: version.clone(),
+                parse_report: parse_report.clone(),
+                fresh_data: fresh_data.clone(),
+                refreshing: refreshing.clone(),
+                stale: stale.clone(),
+            },
+            notify,
+        );
         Finder {
             source,
+            lock_input: None,
             version,
+            parse_report,
+            fresh_data,
+            replacement: None,
+            refreshing,
+            stale,
             searcher,
             #[cfg(test)]
             #[allow(clippy::used_underscore_binding)]
@@ -73,35 +95,95 @@ impl Finder {
         }
     }
 
+    pub(crate) fn with_lock_input(mut self, lock_input: Option<String>) -> Self {
+        self.lock_input = lock_input;
+        self
+    }
+
     pub(crate) fn name(&self) -> String {
-        self.source.to_string()
+        match &self.lock_input {
+            Some(input) => format!("{} [{input}]", self.source),
+            None => self.source.to_string(),
+        }
     }
 
     pub(crate) fn url(&self) -> &str {
         self.source.url()
     }
 
+    /// The releases the source can be switched between, and the index of the one that is loaded
+    pub(crate) fn releases(&self) -> (&[String], Option<usize>) {
+        let releases = self.source.releases();
+        let current = self
+            .source
+            .release
+            .as_ref()
+            .and_then(|r| releases.iter().position(|other| other == r));
+        (releases, current)
+    }
+
     pub(crate) fn version(&self) -> &str {
         self.version
             .get()
             .map_or("Version number not found (yet)", |s| s)
     }
 
+    /// How the data of the source was parsed, once it is loaded
+    pub(crate) fn parse_report(&self) -> Option<&ParseReport> {
+        self.parse_report.get()
+    }
+
+    /// Whether the results are from an outdated cache, while fresh data is fetched in the background
+    pub(crate) fn is_refreshing(&self) -> bool {
+        self.refreshing.load(Ordering::Relaxed)
+    }
+
+    /// Whether the results are from an outdated cache that is not being refreshed, e.g. because it failed or in offline mode
+    pub(crate) fn is_stale(&self) -> bool {
+        self.stale.load(Ordering::Relaxed) && !self.is_refreshing()
+    }
+
     pub(crate) fn init_search(&mut self, pattern: &str, input_status: InputStatus) {
         if input_status != InputStatus::Unchanged {
-            self.searcher.pattern.reparse(
-                0,
-                pattern,
-                CaseMatching::Ignore,
-                Normalization::Smart,
-                // NOTE: As far as I can tell, the optimization that this enables is that if we append to the search string, then any item that had score 0 before will still have score 0, so we don't have to rerun scoring against those items. We still run scoring as usual against all other items.
-                input_status == InputStatus::Append,
-            );
+            reparse(&mut self.searcher, pattern, input_status);
         }
         self.searcher.tick(10);
+
+        // Fresh data is matched next to the old entries, whose results are shown until the new ones are ready
+        let is_new = match self.fresh_data.lock().ok().and_then(|mut f| f.take()) {
+            Some(fresh) => {
+                self.replacement = Some(fresh);
+                true
+            }
+            None => false,
+        };
+        let Some(replacement) = &mut self.replacement else {
+            return;
+        };
+        if is_new {
+            // The new searcher has no pattern yet, so the pattern can't be treated as appended
+            reparse(&mut replacement.searcher, pattern, InputStatus::Change);
+        } else if input_status != InputStatus::Unchanged {
+            reparse(&mut replacement.searcher, pattern, input_status);
+        }
+        if !replacement.searcher.tick(10).running {
+            self.apply_replacement();
+        }
     }
 
-    pub(crate) fn get_results(&self, max: Option<usize>) -> Vec<OptText> {
+    /// Search the fresh data instead of the outdated cached data
+    fn apply_replacement(&mut self) {
+        let Some(replacement) = self.replacement.take() else {
+            return;
+        };
+        debug!(source = %self.source, "Replacing cached data with fresh data");
+        self.searcher = replacement.searcher;
+        self.version = Arc::new(OnceLock::from(replacement.version));
+        self.parse_report = Arc::new(OnceLock::from(replacement.parse_report));
+        self.stale.store(false, Ordering::Relaxed);
+    }
+
+    pub(crate) fn get_results(&self, max: Option<usize>) -> Vec<Entry> {
         let snap = self.searcher.snapshot();
         let n = snap.matched_item_count();
 
@@ -127,53 +209,128 @@ impl Finder {
         &mut self,
         pattern: &str,
         max: Option<usize>,
-    ) -> std::result::Result<Vec<OptText>, Box<dyn std::any::Any + Send + 'static>> {
+    ) -> std::result::Result<Vec<Entry>, Box<dyn std::any::Any + Send + 'static>> {
         self.finish_injection_blocking()?;
         self.init_search(pattern, InputStatus::Change);
+        while self.replacement.is_some() {
+            self.init_search(pattern, InputStatus::Unchanged);
+        }
         while self.searcher.tick(1000).running {}
         Ok(self.get_results(max))
     }
 
-    pub(crate) fn doc_url_to(&self, opt: &OptText) -> String {
-        self.source.doc_url_to(opt)
+    pub(crate) fn doc_url_to(&self, entry: &Entry) -> String {
+        self.source.doc_url_to(entry)
     }
 }
 
+/// Gets the data of a source and passes it on to be searched, along with whether it is outdated.
+/// The data is passed a second time if fresh data replaces outdated cached data.
+type LoadFn = Box<dyn FnOnce(&mut dyn FnMut(SourceData, bool)) -> Result<()> + Send>;
+
+/// A searcher that fresh data was injected into on the loading thread, so that replacing outdated cached data doesn't block the UI
+struct FreshSearcher {
+    searcher: Nucleo<Entry>,
+    version: String,
+    parse_report: ParseReport,
+}
+
+/// Where the loading thread puts what it loaded, besides the entries
+struct Loaded {
+    version: Arc<OnceLock<String>>,
+    parse_report: Arc<OnceLock<ParseReport>>,
+    fresh_data: Arc<Mutex<Option<FreshSearcher>>>,
+    refreshing: Arc<AtomicBool>,
+    stale: Arc<AtomicBool>,
+}
+
 /// Create a searcher with concurrent parsing and injection of data. Getting data (either through HTTP or cached HTML) and injecting it into Nucleo is done in a separate thread, so we can return the searcher quickly instead of blocking.
+/// Fresh data that replaces outdated cached data is injected into a new searcher on the same thread, which is handed over through `Loaded::fresh_data` to be swapped in by the owner of the searcher.
 fn new_searcher(
-    data_fn: Box<dyn FnOnce() -> Result<SourceData> + Send>,
-    version: Arc<OnceLock<String>>,
+    load: LoadFn,
+    loaded: Loaded,
     notify: Arc<dyn Fn() + Sync + Send>,
-) -> (Nucleo<OptText>, JoinHandle<()>) {
-    let mut nuc = Nucleo::<OptText>::new(
-        Config::DEFAULT,
-        notify,
-        // NOTE: There might be room for some optimization in thread allocation here, either by capping the number of threads for each Nucleo instance, or using the multi-column capabilities to merge the instances together.
-        None,
-        1,
-    );
+) -> (Nucleo<Entry>, JoinHandle<()>) {
+    let mut nuc = new_nucleo(notify.clone());
     let inj = nuc.injector();
 
     let handle = std::thread::spawn(move || {
-        let opts = if let Ok(data) = data_fn() {
-            version.get_or_init(|| data.version);
-            data.opts
-        } else {
-            version.get_or_init(|| "Failed to get data".to_string());
-            vec![]
-        };
-
-        for d in opts {
-            // TODO: Add the right data to search string
-            // NOTE: First argument is the "data" part of matched items; use it to store the data you want to get out at the end (e.g. the entire object you're searching for, or an index to it).
-            // The second argument is a closure that outputs the text that should be displayed as the user, and which Nucleo matches a given pattern against. For us, that could be the contents of the various fields of OptData in different columns
-            inj.push(d, |data, col| col[0] = data.name.clone().into());
+        let mut shown = false;
+        let res = load(&mut |mut data, stale| {
+            if shown {
+                debug!(source = %data.source, entries = data.entry_count(), "Injecting fresh source data");
+                let fresh = FreshSearcher {
+                    searcher: new_nucleo(notify.clone()),
+                    version: std::mem::take(&mut data.version),
+                    parse_report: std::mem::take(&mut data.parse_report),
+                };
+                inject(&fresh.searcher.injector(), data);
+                if let Ok(mut slot) = loaded.fresh_data.lock() {
+                    *slot = Some(fresh);
+                }
+                notify();
+                return;
+            }
+            shown = true;
+            loaded.stale.store(stale, Ordering::Relaxed);
+            // Until loading finishes, outdated data may still be replaced
+            loaded.refreshing.store(stale, Ordering::Relaxed);
+            debug!(source = %data.source, entries = data.entry_count(), "Injecting source data");
+            loaded
+                .version
+                .get_or_init(|| std::mem::take(&mut data.version));
+            loaded
+                .parse_report
+                .get_or_init(|| std::mem::take(&mut data.parse_report));
+            inject(&inj, data);
+        });
+        loaded.refreshing.store(false, Ordering::Relaxed);
+        if let Err(err) = res {
+            debug!(?err);
+            if !shown {
+                let message = if err.is::<Offline>() {
+                    "Not cached, and offline"
+                } else {
+                    "Failed to get data"
+                };
+                loaded.version.get_or_init(|| message.to_string());
+            }
         }
     });
     nuc.tick(0);
     (nuc, handle)
 }
 
+fn new_nucleo(notify: Arc<dyn Fn() + Sync + Send>) -> Nucleo<Entry> {
+    Nucleo::<Entry>::new(
+        Config::DEFAULT,
+        notify,
+        // NOTE: There might be room for some optimization in thread allocation here, either by capping the number of threads for each Nucleo instance, or using the multi-column capabilities to merge the instances together.
+        None,
+        1,
+    )
+}
+
+fn reparse(searcher: &mut Nucleo<Entry>, pattern: &str, input_status: InputStatus) {
+    searcher.pattern.reparse(
+        0,
+        pattern,
+        CaseMatching::Ignore,
+        Normalization::Smart,
+        // NOTE: As far as I can tell, the optimization that this enables is that if we append to the search string, then any item that had score 0 before will still have score 0, so we don't have to rerun scoring against those items. We still run scoring as usual against all other items.
+        input_status == InputStatus::Append,
+    );
+}
+
+fn inject(inj: &Injector<Entry>, data: SourceData) {
+    for d in data.into_entries() {
+        // TODO: Add the right data to search string
+        // NOTE: First argument is the "data" part of matched items; use it to store the data you want to get out at the end (e.g. the entire object you're searching for, or an index to it).
+        // The second argument is a closure that outputs the text that should be displayed as the user, and which Nucleo matches a given pattern against. For us, that could be the contents of the various fields of OptData in different columns
+        inj.push(d, |data, col| col[0] = data.name().into());
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -202,7 +359,8 @@ mod tests {
             // Nix-Darwin
             let data = swh.data.clone();
             let data_fn = Box::new(move || Ok(data.clone()));
-            let mut f = Finder::new_with_data_fn(swh.source.clone(), Some(data_fn), None, None);
+            let mut f =
+                Finder::new_with_data_fn(swh.source.clone(), Some(data_fn), CacheConfig::default());
             assert_eq!(
             f.find_blocking("asdfasdfasdf", Some(5))
                 .expect("find blocking should not fail")
@@ -212,4 +370,76 @@ mod tests {
         );
         }
     }
+
+    #[test]
+    fn outdated_cache_is_replaced() -> Result<()> {
+        use crate::test_utils::{local_options_source, outdated};
+
+        let tmpdir = tempfile::tempdir().expect("Can create temporary directory");
+        let source = Source::from(&local_options_source(tmpdir.path()));
+        let cache_file = source.cache_file(tmpdir.path());
+        Source::store_cache(&source.get_online_data()?, &cache_file)?;
+        outdated(&cache_file)?;
+        std::fs::write(
+            tmpdir.path().join("options.json"),
+            r#"{"programs.bar.enable": {"type": "boolean"}}"#,
+        )?;
+
+        let mut finder = Finder::new(
+            source,
+            CacheConfig {
+                file: Some(cache_file),
+                ..CacheConfig::default()
+            },
+        );
+        finder
+            .finish_injection_blocking()
+            .expect("Loading succeeds");
+        // The cached entries are searched until the fresh ones are swapped in
+        assert!(finder.fresh_data.lock().is_ok_and(|d| d.is_some()));
+        assert!(!finder.is_refreshing());
+        let results = finder
+            .find_blocking("enable", None)
+            .expect("Search succeeds");
+        assert_eq!(
+            results.iter().map(Entry::name).collect::<Vec<_>>(),
+            ["programs.bar.enable"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn only_outdated_data_is_refreshing() -> Result<()> {
+        use crate::test_utils::local_options_source;
+        use std::sync::mpsc;
+
+        let tmpdir = tempfile::tempdir().expect("Can create temporary directory");
+        let data = Source::from(&local_options_source(tmpdir.path())).get_online_data()?;
+        for stale in [false, true] {
+            let (done, wait) = mpsc::channel::<()>();
+            let data = data.clone();
+            let loaded = Loaded {
+                version: Arc::new(OnceLock::new()),
+                parse_report: Arc::new(OnceLock::new()),
+                fresh_data: Arc::new(Mutex::new(None)),
+                refreshing: Arc::new(AtomicBool::new(false)),
+                stale: Arc::new(AtomicBool::new(false)),
+            };
+            let (version, refreshing) = (loaded.version.clone(), loaded.refreshing.clone());
+            let load: LoadFn = Box::new(move |show| {
+                show(data, stale);
+                wait.recv().ok();
+                Ok(())
+            });
+            let (_searcher, handle) = new_searcher(load, loaded, Arc::new(|| {}));
+            while version.get().is_none() {
+                std::thread::yield_now();
+            }
+            assert_eq!(refreshing.load(Ordering::Relaxed), stale);
+            done.send(()).ok();
+            handle.join().expect("Loading succeeds");
+            assert!(!refreshing.load(Ordering::Relaxed));
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,118 @@
+use crate::parsing::{FunText, RichText};
+use crate::rich_text_display::{rich_text_lines, wrapped_height};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Padding, Paragraph, Wrap},
+};
+
+/// A widget to display a single function, either a built-in of the Nix language or one of the Nixpkgs library.
+/// Layout:
+/// ######################################################
+/// # Name: ...          Signature: .................... #
+/// # Description: ..................................... #
+/// #     ................................................ #
+/// # Type: ............................................ #
+/// # Example: ......................................... #
+/// ######################################################
+/// The type and example are only shown if they are documented.
+
+#[derive(Clone)]
+pub struct FunListItem {
+    pub content: FunText,
+    style: Style,
+}
+
+impl FunListItem {
+    const DEFAULT_HEIGHT: u16 = 4;
+
+    pub fn new(value: FunText) -> Self {
+        FunListItem {
+            content: value,
+            style: Style::default(),
+        }
+    }
+}
+
+impl Widget for FunListItem {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let title_style = Style::new().blue();
+
+        let name = Paragraph::new(Line::from(vec![
+            Span::styled("Name: ", title_style),
+            self.content.name.clone().into(),
+        ]));
+        let signature = Paragraph::new(Line::from(vec![
+            Span::styled("Signature: ", title_style),
+            self.content.signature().into(),
+        ]));
+        let description = Paragraph::new(self.description_lines()).wrap(Wrap { trim: false });
+
+        let block = Block::default()
+            .style(self.style)
+            .padding(Padding::bottom(1));
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let outer = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Percentage(100)])
+            .split(inner);
+
+        let inner_top = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Ratio(1, 3), Constraint::Ratio(2, 3)])
+            .split(outer[0]);
+
+        name.render(inner_top[0], buf);
+        signature.render(inner_top[1], buf);
+
+        description.render(outer[1], buf);
+    }
+}
+
+impl FunListItem {
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn pre_render(&mut self, context: &tui_widget_list::ListBuildContext) -> u16 {
+        self.style = if context.is_selected {
+            Style::default().bg(Color::DarkGray)
+        } else if context.index % 2 == 0 {
+            Style::default()
+        } else {
+            Style::default().bg(Color::Indexed(236))
+        };
+        self.full_height(context.cross_axis_size)
+    }
+}
+
+impl FunListItem {
+    fn full_height(&self, width: u16) -> u16 {
+        // The description spans the full width.
+        let description_height = wrapped_height(&self.description_lines(), width);
+
+        (description_height + 2).max(FunListItem::DEFAULT_HEIGHT)
+    }
+
+    fn description_lines(&self) -> Vec<Line<'static>> {
+        let title_style = Style::new().blue();
+        let mut lines = rich_text_lines(
+            Span::styled("Description: ", title_style),
+            &self.content.rich_description,
+        );
+        if !self.content.fun_type.is_empty() {
+            lines.extend(rich_text_lines(
+                Span::styled("Type: ", title_style),
+                &RichText::code(&self.content.fun_type),
+            ));
+        }
+        if !self.content.rich_example.is_empty() {
+            lines.extend(rich_text_lines(
+                Span::styled("Example: ", title_style),
+                &self.content.rich_example,
+            ));
+        }
+        lines
+    }
+}
@@ -1,31 +1,40 @@
 use clap::Parser;
 use color_eyre::eyre::Result;
 use config::AppConfig;
+use std::process::ExitCode;
 use std::sync::OnceLock;
 
 mod app;
 mod cli;
 use cli::Cli;
 mod cache;
+mod cache_commands;
 mod config;
+mod diff;
+mod doctor;
 mod finder;
+mod fun_display;
 mod logging;
 mod opt_display;
 mod parsing;
+mod pkg_display;
+mod rich_text_display;
 mod source;
 mod tui;
 
 #[cfg(test)]
 mod test_utils;
 
-fn main() {
+fn main() -> ExitCode {
     let res = init_and_run();
     if let Err(e) = tui::restore() {
         eprintln!("{e:#?}");
     }
     if let Err(e) = res {
         eprintln!("{e:#?}");
+        return ExitCode::FAILURE;
     }
+    ExitCode::SUCCESS
 }
 
 fn init_and_run() -> Result<()> {
@@ -38,11 +47,13 @@ fn init_and_run() -> Result<()> {
     let cli = Cli::parse();
 
     // Get a static config object to pass around references to. This is needed e.g. in search::new_searcher, where stuff like the cache configuration is used to construct a data_fn, a closure that gets passed to a new thread eventually
-    let config = config::initialize(&cli)?;
+    let mut config = config::initialize(&cli)?;
+    logging::initialize(&config)?;
+    // After logging is set up, so that an unreadable `flake.lock` is logged
+    config::pin_to_flake_lock(&mut config);
     CONFIG.set(config).expect("Can set OnceCell once");
     let config = CONFIG.get().expect("Can get value of just-set OnceCell");
 
-    logging::initialize(config)?;
     cache::initialize_cache_dir(config)?;
 
     cli.run(config)?;
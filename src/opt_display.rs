@@ -1,4 +1,7 @@
-use crate::parsing::OptText;
+use crate::fun_display::FunListItem;
+use crate::parsing::{Entry, OptText};
+use crate::pkg_display::PkgListItem;
+use crate::rich_text_display::{rich_text_lines, wrapped_height};
 use ratatui::{
     prelude::*,
     widgets::{Block, Padding, Paragraph, Wrap},
@@ -10,11 +13,16 @@ use ratatui::{
 /// # Name: ...          Type: ...          Default: ... #
 /// # Description: ...............          Example: ... #
 /// #     ........................              ........ #
+/// # Choices:                                           #
+/// #   • ...                                            #
 /// ######################################################
+/// The choices are only shown for options with an enum type.
 
 #[derive(Clone)]
 pub struct OptListItem {
     pub content: OptText,
+    /// The allowed values of an enum type
+    choices: Vec<String>,
     style: Style,
 }
 
@@ -22,13 +30,58 @@ impl OptListItem {
     const DEFAULT_HEIGHT: u16 = 4;
 
     pub fn new(value: OptText) -> Self {
+        let choices = value
+            .opt_type()
+            .and_then(|t| t.choices().map(<[String]>::to_vec))
+            .unwrap_or_default();
         OptListItem {
             content: value,
+            choices,
             style: Style::default(),
         }
     }
 }
 
+impl OptListItem {
+    /// The name with placeholders like `<name>` and `*` set apart from the attribute names
+    fn name_spans(&self) -> Vec<Span<'static>> {
+        let placeholder_style = Style::new().italic().dark_gray();
+        let mut spans = vec![];
+        for (i, segment) in self.content.loc.iter().enumerate() {
+            if i > 0 {
+                spans.push(".".into());
+            }
+            if segment.is_placeholder() {
+                spans.push(Span::styled(segment.to_string(), placeholder_style));
+            } else {
+                spans.push(segment.to_string().into());
+            }
+        }
+        spans
+    }
+
+    fn description_lines(&self) -> Vec<Line<'static>> {
+        let title_style = Style::new().blue();
+        let mut lines = rich_text_lines(
+            Span::styled("Description: ", title_style),
+            &self.content.rich_description,
+        );
+        if !self.choices.is_empty() {
+            lines.push(Line::styled("Choices:", title_style));
+            lines.extend(self.choices.iter().map(|c| Line::from(format!("  • {c}"))));
+        }
+        lines
+    }
+
+    /// Examples keep their line breaks, so multi-line Nix expressions stay readable
+    fn example_lines(&self) -> Vec<Line<'static>> {
+        rich_text_lines(
+            Span::styled("Example: ", Style::new().blue()),
+            &self.content.rich_example,
+        )
+    }
+}
+
 impl Widget for OptListItem {
     fn render(self, area: Rect, buf: &mut Buffer)
     where
@@ -36,10 +89,11 @@ impl Widget for OptListItem {
     {
         let title_style = Style::new().blue();
 
-        let name = Paragraph::new(Line::from(vec![
-            Span::styled("Name: ", title_style),
-            self.content.name.clone().into(),
-        ]));
+        let name = Paragraph::new(Line::from(
+            std::iter::once(Span::styled("Name: ", title_style))
+                .chain(self.name_spans())
+                .collect::<Vec<_>>(),
+        ));
         let var_type = Paragraph::new(Line::from(vec![
             Span::styled("Type: ", title_style),
             self.content.var_type.clone().into(),
@@ -48,16 +102,8 @@ impl Widget for OptListItem {
             Span::styled("Default: ", title_style),
             self.content.default.clone().into(),
         ]));
-        let description = Paragraph::new(Line::from(vec![
-            Span::styled("Description: ", title_style),
-            self.content.description.clone().into(),
-        ]))
-        .wrap(Wrap { trim: true });
-        let example = Paragraph::new(Line::from(vec![
-            Span::styled("Example: ", title_style),
-            self.content.example.clone().into(),
-        ]))
-        .wrap(Wrap { trim: true });
+        let description = Paragraph::new(self.description_lines()).wrap(Wrap { trim: false });
+        let example = Paragraph::new(self.example_lines()).wrap(Wrap { trim: false });
 
         let block = Block::default()
             .style(self.style)
@@ -105,13 +151,56 @@ impl OptListItem {
 impl OptListItem {
     fn full_height(&self, width: u16) -> u16 {
         // Description and example fields are laid out next to each other at a 2:1 ratio.
+        let description_height = wrapped_height(&self.description_lines(), width * 2 / 3);
+        let example_height = wrapped_height(&self.example_lines(), width / 3);
 
-        #[allow(clippy::cast_possible_truncation)]
-        let description_height = (self.content.description.len() as u16 * 3) / (width * 2);
-        #[allow(clippy::cast_possible_truncation)]
-        let example_height = (self.content.example.len() as u16 * 3) / width;
+        (description_height.max(example_height) + 2).max(OptListItem::DEFAULT_HEIGHT)
+    }
+}
 
-        // Integer division truncates decimals
-        (description_height.max(example_height) + 3).max(OptListItem::DEFAULT_HEIGHT)
+/// A list item for any kind of [`Entry`], rendered with the widget for that kind.
+#[derive(Clone)]
+pub enum EntryListItem {
+    Opt(OptListItem),
+    Pkg(PkgListItem),
+    Fun(FunListItem),
+}
+
+impl EntryListItem {
+    pub fn new(value: Entry) -> Self {
+        match value {
+            Entry::Opt(opt) => EntryListItem::Opt(OptListItem::new(opt)),
+            Entry::Pkg(pkg) => EntryListItem::Pkg(PkgListItem::new(pkg)),
+            Entry::Fun(fun) => EntryListItem::Fun(FunListItem::new(fun)),
+        }
+    }
+
+    pub fn content(&self) -> Entry {
+        match self {
+            EntryListItem::Opt(item) => Entry::Opt(item.content.clone()),
+            EntryListItem::Pkg(item) => Entry::Pkg(item.content.clone()),
+            EntryListItem::Fun(item) => Entry::Fun(item.content.clone()),
+        }
+    }
+
+    pub fn pre_render(&mut self, context: &tui_widget_list::ListBuildContext) -> u16 {
+        match self {
+            EntryListItem::Opt(item) => item.pre_render(context),
+            EntryListItem::Pkg(item) => item.pre_render(context),
+            EntryListItem::Fun(item) => item.pre_render(context),
+        }
+    }
+}
+
+impl Widget for EntryListItem {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        match self {
+            EntryListItem::Opt(item) => item.render(area, buf),
+            EntryListItem::Pkg(item) => item.render(area, buf),
+            EntryListItem::Fun(item) => item.render(area, buf),
+        }
     }
 }
@@ -0,0 +1,170 @@
+use super::{descendant_lists, entry_label, ParseReport, RichText};
+use bitcode::{Decode, Encode};
+use color_eyre::eyre::{ensure, Result};
+use std::collections::HashSet;
+use tl::{HTMLTag, NodeHandle, Parser, ParserOptions};
+
+/// A fully parsed function (or constant), either a built-in of the Nix language or a function of the Nixpkgs library
+#[derive(Clone, Debug, Encode, Decode, PartialEq)]
+pub(crate) struct FunText {
+    pub(crate) id: String,
+    /// The full attribute path, e.g. `builtins.foldl'`
+    pub(crate) name: String,
+    /// The names of the arguments, in order. Empty for constants like `builtins.nixVersion`.
+    pub(crate) args: Vec<String>,
+    /// The documented type signature, e.g. `concatStrings :: [string] -> string`. Empty for built-ins, which don't document one.
+    pub(crate) fun_type: String,
+    pub(crate) rich_description: RichText,
+    pub(crate) rich_example: RichText,
+    pub(crate) declared_by_urls: Vec<String>,
+}
+
+impl FunText {
+    /// The function applied to its argument names, e.g. `builtins.foldl' op nul list`
+    pub(crate) fn signature(&self) -> String {
+        std::iter::once(self.name.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Structure of the Nix manual's built-ins page: A single `<dl>` where each function is a `<dt id="builtins-<name>">` containing `<code>name <var>arg1</var> <var>arg2</var></code>`, followed by a `<dd>` with the description.
+/// Terms and descriptions are paired by adjacency, so a term without a description is skipped instead of shifting every later pair. Lists nested in descriptions are part of their function.
+pub(crate) fn parse_builtins(html: &str) -> Result<(Vec<FunText>, ParseReport)> {
+    let dom = tl::parse(html, ParserOptions::default())?;
+    let p = dom.parser();
+
+    let mut funs = vec![];
+    let mut report = ParseReport::default();
+    let mut nested = HashSet::new();
+    for dl in dom
+        .query_selector("dl")
+        .expect("dl is a valid CSS selector")
+    {
+        if nested.contains(&dl.get_inner()) {
+            continue;
+        }
+        let Some(dl) = dl.get(p).and_then(tl::Node::as_tag) else {
+            continue;
+        };
+        let mut term: Option<NodeHandle> = None;
+        for &child in dl.children().top().iter() {
+            let Some(tag) = child.get(p).and_then(tl::Node::as_tag) else {
+                continue;
+            };
+            match tag.name().as_utf8_str().as_ref() {
+                "dt" => {
+                    if let Some(dt) = term.replace(child) {
+                        report.skip(entry_label(dt, p), "term without a description");
+                    }
+                }
+                "dd" => {
+                    nested.extend(descendant_lists(tag, p));
+                    let Some(dt) = term.take() else {
+                        report.skip(entry_label(child, p), "description without a term");
+                        continue;
+                    };
+                    match parse_builtin(dt, tag, p) {
+                        Some(fun) => {
+                            funs.push(fun);
+                            report.entries += 1;
+                        }
+                        None => report.skip(entry_label(dt, p), "malformed built-in"),
+                    }
+                }
+                _ => (),
+            }
+        }
+        if let Some(dt) = term {
+            report.skip(entry_label(dt, p), "term without a description");
+        }
+    }
+    ensure!(!funs.is_empty(), "Found no built-in functions");
+    Ok((funs, report))
+}
+
+fn parse_builtin(dt: NodeHandle, dd: &HTMLTag, p: &Parser) -> Option<FunText> {
+    let dt = dt.get(p)?.as_tag()?;
+    let id = dt.attributes().id()?.as_utf8_str().to_string();
+    let short_name = id.strip_prefix("builtins-")?;
+    let args = dt
+        .query_selector(p, "var")?
+        .filter_map(|nh| nh.get(p))
+        .map(|n| n.inner_text(p).trim().to_string())
+        .collect();
+    Some(FunText {
+        name: format!("builtins.{short_name}"),
+        id,
+        args,
+        fun_type: String::new(),
+        rich_description: RichText::from_html(&dd.inner_html(p)),
+        rich_example: RichText::default(),
+        declared_by_urls: vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUILTINS_HTML: &str = r##"<dl>
+<dt id="builtins-currentSystem">
+  <a href="#builtins-currentSystem"><code>currentSystem</code></a>
+</dt>
+<dd>
+<p>The value of the <code>system</code> setting.</p>
+</dd>
+<dt id="builtins-foldl'">
+  <a href="#builtins-foldl'"><code>foldl' <var>op</var> <var>nul</var> <var>list</var></code></a>
+</dt>
+<dd>
+<p>Reduce a list by applying a binary operator, from left to right.</p>
+<dl>
+<dt><code>op</code></dt>
+<dd><p>The operator, called with the accumulator and each element.</p></dd>
+</dl>
+</dd>
+</dl>"##;
+
+    #[test]
+    fn parse_builtins_signatures() -> Result<()> {
+        let (funs, report) = parse_builtins(BUILTINS_HTML)?;
+        assert_eq!(funs.len(), 2);
+        assert_eq!(report, ParseReport::complete(2));
+
+        assert_eq!(funs[0].name, "builtins.currentSystem");
+        assert!(funs[0].args.is_empty());
+        assert_eq!(funs[0].signature(), "builtins.currentSystem");
+
+        let foldl = &funs[1];
+        assert_eq!(foldl.id, "builtins-foldl'");
+        assert_eq!(foldl.name, "builtins.foldl'");
+        assert_eq!(foldl.args, vec!["op", "nul", "list"]);
+        assert_eq!(foldl.signature(), "builtins.foldl' op nul list");
+        assert!(foldl
+            .rich_description
+            .to_plain()
+            .starts_with("Reduce a list by applying a binary operator, from left to right."));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_builtins_reports_malformed_entries() -> Result<()> {
+        let functions = BUILTINS_HTML.strip_suffix("</dl>").unwrap_or_default();
+        let html =
+            format!("{functions}<dt><code>unnamed</code></dt>\n<dd><p>No anchor.</p></dd>\n</dl>");
+        let (funs, report) = parse_builtins(&html)?;
+        assert_eq!(funs.len(), 2);
+        assert_eq!(report.entries, 2);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].label, "unnamed");
+        assert_eq!(report.skipped[0].reason, "malformed built-in");
+        Ok(())
+    }
+
+    #[test]
+    fn parse_builtins_rejects_options_page() {
+        assert!(parse_builtins("<html><body><p>No functions here</p></body></html>").is_err());
+    }
+}
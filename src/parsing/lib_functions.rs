@@ -0,0 +1,243 @@
+use super::{read_html_strip_prefix, Block, FunText, RichText, Span};
+use color_eyre::eyre::Result;
+use tl::{HTMLTag, NodeHandle, Parser, ParserOptions};
+use tracing::trace;
+
+/// Structure of the function reference in the Nixpkgs manual (<https://nixos.org/manual/nixpkgs/stable/#sec-functions-library>): Each library (e.g. `lib.strings`) is a `<div class="section">` containing one nested section per function.
+/// A function section starts with a title containing an anchor `function-library-<name>` and the name in a `<code>` tag, followed by the description, nested sections for the type signature, inputs and examples, and a "Located at" paragraph linking to the source.
+pub(crate) fn parse_lib_functions(html: &str) -> Result<Vec<FunText>> {
+    let dom = tl::parse(html, ParserOptions::default())?;
+    let p = dom.parser();
+    Ok(dom
+        .query_selector("div.section")
+        .expect("div.section is a valid CSS selector")
+        .filter_map(|section| LibFunctionParser::new(section, p).parse())
+        .collect())
+}
+
+#[derive(Debug)]
+struct LibFunctionParser<'a> {
+    section: NodeHandle,
+    p: &'a Parser<'a>,
+}
+
+impl<'dom> LibFunctionParser<'dom> {
+    const ANCHOR_SELECTOR: &'static str = r#"a[id^="function-library-"]"#;
+
+    fn new(section: NodeHandle, p: &'dom Parser) -> LibFunctionParser<'dom> {
+        LibFunctionParser { section, p }
+    }
+
+    /// Returns `None` if the section is not a function section, e.g. a library section or a subsection of a function.
+    fn parse(self) -> Option<FunText> {
+        let section = self.section.get(self.p)?.as_tag()?;
+        let children = self.child_tags(section);
+        let (title, body) = children.split_first()?;
+        if !title.attributes().is_class_member("titlepage") {
+            return None;
+        }
+        // Subsections (Type, Inputs, Examples) share the anchor prefix, but only functions have their name in a `<code>` tag.
+        let id = self
+            .first_tag(title, Self::ANCHOR_SELECTOR)?
+            .attributes()
+            .id()?
+            .as_utf8_str()
+            .to_string();
+        let name = self.to_text(&[self.first_tag(title, "code")?]);
+
+        let mut description = vec![];
+        let mut inputs = vec![];
+        let mut fun_type = String::new();
+        let mut examples = vec![];
+        let mut declared_by_urls = vec![];
+
+        for &tag in body {
+            if tag.attributes().is_class_member("section") {
+                let sub_children = self.child_tags(tag);
+                let Some((sub_title, sub_body)) = sub_children.split_first() else {
+                    continue;
+                };
+                match self.to_text(&[sub_title]).as_str() {
+                    "Type" => fun_type = self.code_blocks(sub_body).join("\n"),
+                    "Inputs" | "Arguments" => inputs.extend(self.definitions(sub_body)),
+                    "Examples" | "Example" => examples.extend(self.code_blocks(sub_body)),
+                    _ => description.extend(sub_body),
+                }
+            } else if tag.attributes().is_class_member("example") {
+                examples.extend(self.code_blocks(&[tag]));
+            } else if tag
+                .inner_text(self.p)
+                .trim_start()
+                .starts_with("Located at")
+            {
+                declared_by_urls = self.hrefs(tag);
+            } else {
+                description.push(tag);
+            }
+        }
+
+        let mut rich_description = RichText::from_html(&self.to_html(&description));
+        if !inputs.is_empty() {
+            rich_description
+                .0
+                .push(Block::Paragraph(vec![Span::plain("Inputs:")]));
+            rich_description
+                .0
+                .extend(inputs.iter().map(|(term, definition)| {
+                    Block::ListItem(vec![Span::plain(&format!("{term}: {definition}"))])
+                }));
+        }
+        trace!(name: "Parsed lib function", id, name);
+        Some(FunText {
+            id,
+            name,
+            args: inputs.into_iter().map(|(term, _)| term).collect(),
+            fun_type,
+            rich_description,
+            rich_example: RichText(examples.into_iter().map(Block::Code).collect()),
+            declared_by_urls,
+        })
+    }
+
+    fn child_tags(&self, tag: &'dom HTMLTag<'dom>) -> Vec<&'dom HTMLTag<'dom>> {
+        tag.children()
+            .top()
+            .iter()
+            .filter_map(|n| n.get(self.p)?.as_tag())
+            .collect()
+    }
+
+    fn first_tag(&self, tag: &'dom HTMLTag<'dom>, selector: &str) -> Option<&'dom HTMLTag<'dom>> {
+        tag.query_selector(self.p, selector)?
+            .next()?
+            .get(self.p)?
+            .as_tag()
+    }
+
+    /// Every `name` tag in `tags` or their descendants
+    fn tags_named(&self, tags: &[&'dom HTMLTag<'dom>], name: &str) -> Vec<&'dom HTMLTag<'dom>> {
+        tags.iter()
+            .flat_map(|&t| {
+                if t.name() == name {
+                    vec![t]
+                } else {
+                    t.query_selector(self.p, name)
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|nh| nh.get(self.p)?.as_tag())
+                        .collect()
+                }
+            })
+            .collect()
+    }
+
+    fn to_html(&self, tags: &[&HTMLTag]) -> String {
+        tags.iter()
+            .map(|t| t.outer_html(self.p))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn to_text(&self, tags: &[&HTMLTag]) -> String {
+        read_html_strip_prefix(&self.to_html(tags), None)
+    }
+
+    /// The contents of every `<pre>` block in `tags`
+    fn code_blocks(&self, tags: &[&'dom HTMLTag<'dom>]) -> Vec<String> {
+        self.tags_named(tags, "pre")
+            .into_iter()
+            .map(|pre| self.to_text(&[pre]))
+            .collect()
+    }
+
+    /// The `<dt>`/`<dd>` pairs of each `<dl>` in `tags`, as `(term, definition)`.
+    /// Pairs are matched within each list by adjacency, so a missing `<dd>` only drops that term.
+    fn definitions(&self, tags: &[&'dom HTMLTag<'dom>]) -> Vec<(String, String)> {
+        let mut definitions = vec![];
+        for dl in self.tags_named(tags, "dl") {
+            let mut term = None;
+            for tag in self.child_tags(dl) {
+                match tag.name().as_utf8_str().as_ref() {
+                    "dt" => term = Some(self.to_text(&[tag])),
+                    "dd" => {
+                        if let Some(term) = term.take() {
+                            definitions.push((term, self.to_text(&[tag])));
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        definitions
+    }
+
+    fn hrefs(&self, tag: &'dom HTMLTag<'dom>) -> Vec<String> {
+        self.tags_named(&[tag], "a")
+            .into_iter()
+            .filter_map(|a| a.attributes().get("href").flatten())
+            .map(|href| href.as_utf8_str().to_string())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIB_HTML: &str = r#"<html><body>
+<div class="section"><div class="titlepage"><div><div><h3 class="title"><a id="sec-functions-library-strings"></a>lib.strings: string manipulation functions</h3></div></div></div>
+<div class="section"><div class="titlepage"><div><div><h4 class="title"><a id="function-library-lib.strings.concatStrings"></a><code class="function">lib.strings.concatStrings</code></h4></div></div></div>
+<p>Concatenate a list of strings.</p>
+<div class="section"><div class="titlepage"><div><div><h5 class="title"><a id="function-library-lib.strings.concatStrings-type"></a>Type</h5></div></div></div>
+<pre><code class="programlisting">concatStrings :: [string] -&gt; string</code></pre>
+</div>
+<div class="section"><div class="titlepage"><div><div><h5 class="title"><a id="function-library-lib.strings.concatStrings-inputs"></a>Inputs</h5></div></div></div>
+<dl class="variablelist"><dt><span class="term"><code class="literal">list</code></span></dt><dd><p>List of strings to concatenate</p></dd></dl>
+</div>
+<div class="section"><div class="titlepage"><div><div><h5 class="title"><a id="function-library-lib.strings.concatStrings-examples"></a>Examples</h5></div></div></div>
+<div class="example"><a id="function-library-lib.strings.concatStrings-example"></a><p class="title"><strong>Example 1. <code class="literal">lib.strings.concatStrings</code> usage example</strong></p><div class="example-contents"><pre><code class="programlisting nix">concatStrings ["foo" "bar"]
+=&gt; "foobar"</code></pre></div></div>
+</div>
+<p>Located at <a class="link" href="https://github.com/NixOS/nixpkgs/blob/master/lib/strings.nix#L93" target="_top">lib/strings.nix:93</a> in <code class="literal">&lt;nixpkgs&gt;</code>.</p>
+</div>
+<div class="section"><div class="titlepage"><div><div><h4 class="title"><a id="function-library-lib.strings.toUpper"></a><code class="function">lib.strings.toUpper</code></h4></div></div></div>
+<p>Converts an ASCII string <code class="literal">s</code> to upper-case.</p>
+</div>
+</div>
+</body></html>"#;
+
+    #[test]
+    fn parse_lib_function_sections() -> Result<()> {
+        let funs = parse_lib_functions(LIB_HTML)?;
+        assert_eq!(funs.len(), 2, "{funs:#?}");
+
+        let concat = &funs[0];
+        assert_eq!(concat.id, "function-library-lib.strings.concatStrings");
+        assert_eq!(concat.name, "lib.strings.concatStrings");
+        assert_eq!(concat.fun_type, "concatStrings :: [string] -> string");
+        assert_eq!(concat.signature(), "lib.strings.concatStrings list");
+        assert_eq!(
+            concat.rich_description.to_plain(),
+            "Concatenate a list of strings.\nInputs:\n• list: List of strings to concatenate"
+        );
+        assert_eq!(
+            concat.rich_example,
+            RichText::code("concatStrings [\"foo\" \"bar\"]\n=> \"foobar\"")
+        );
+        assert_eq!(
+            concat.declared_by_urls,
+            vec!["https://github.com/NixOS/nixpkgs/blob/master/lib/strings.nix#L93"]
+        );
+
+        let upper = &funs[1];
+        assert_eq!(upper.name, "lib.strings.toUpper");
+        assert_eq!(
+            upper.rich_description.to_plain(),
+            "Converts an ASCII string s to upper-case."
+        );
+        assert_eq!(upper.fun_type, "");
+        assert!(upper.rich_example.is_empty());
+        assert!(upper.declared_by_urls.is_empty());
+        Ok(())
+    }
+}
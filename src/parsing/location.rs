@@ -0,0 +1,141 @@
+use bitcode::{Decode, Encode};
+use std::fmt;
+
+/// One segment of the attribute path of an option, e.g. of `services.nginx.virtualHosts.<name>.listen.*.addr`
+#[derive(Clone, Debug, Encode, Decode, PartialEq, Eq)]
+pub(crate) enum LocSegment {
+    Attr(String),
+    /// Stands for any attribute name of an `attrsOf`, holding the word between the angle brackets, e.g. `name` for `<name>`
+    Placeholder(String),
+    /// Stands for any element of a `listOf`, written `*`
+    Element,
+}
+
+impl LocSegment {
+    pub(crate) fn is_placeholder(&self) -> bool {
+        !matches!(self, Self::Attr(_))
+    }
+}
+
+/// Classifies a single segment as written in the `loc` of an `options.json`, i.e. without quotes
+impl From<&str> for LocSegment {
+    fn from(segment: &str) -> Self {
+        if segment == "*" {
+            Self::Element
+        } else if let Some(word) = segment.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+            Self::Placeholder(word.to_string())
+        } else {
+            Self::Attr(segment.to_string())
+        }
+    }
+}
+
+impl fmt::Display for LocSegment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Attr(attr) if is_identifier(attr) => write!(f, "{attr}"),
+            Self::Attr(attr) => write!(f, "{attr:?}"),
+            Self::Placeholder(word) => write!(f, "<{word}>"),
+            Self::Element => write!(f, "*"),
+        }
+    }
+}
+
+fn is_identifier(attr: &str) -> bool {
+    let mut chars = attr.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || "_-'".contains(c))
+}
+
+/// Split an option name into its segments. Dots inside quotes (`boot.kernel.sysctl."net.core.rmem_max"`) or angle brackets don't separate segments.
+pub(crate) fn parse_location(name: &str) -> Vec<LocSegment> {
+    let mut segments = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut was_quoted = false;
+    let mut brackets = 0_usize;
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                was_quoted = true;
+            }
+            '\\' if quoted => current.extend(chars.next()),
+            '<' if !quoted => {
+                brackets += 1;
+                current.push(c);
+            }
+            '>' if !quoted => {
+                brackets = brackets.saturating_sub(1);
+                current.push(c);
+            }
+            '.' if !quoted && brackets == 0 => {
+                segments.push(segment(std::mem::take(&mut current), was_quoted));
+                was_quoted = false;
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() || was_quoted {
+        segments.push(segment(current, was_quoted));
+    }
+    segments
+}
+
+/// Quoted segments are always attribute names, even `"*"`
+fn segment(text: String, quoted: bool) -> LocSegment {
+    if quoted {
+        LocSegment::Attr(text)
+    } else {
+        LocSegment::from(text.as_str())
+    }
+}
+
+/// The option name made up of `loc`, the inverse of `parse_location`
+pub(crate) fn format_location(loc: &[LocSegment]) -> String {
+    loc.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_location_segments() {
+        use LocSegment::{Attr, Element, Placeholder};
+
+        let name = "services.nginx.virtualHosts.<name>.listen.*.addr";
+        let loc = parse_location(name);
+        assert_eq!(
+            loc,
+            vec![
+                Attr("services".to_string()),
+                Attr("nginx".to_string()),
+                Attr("virtualHosts".to_string()),
+                Placeholder("name".to_string()),
+                Attr("listen".to_string()),
+                Element,
+                Attr("addr".to_string()),
+            ]
+        );
+        assert_eq!(format_location(&loc), name);
+
+        let name = r#"boot.kernel.sysctl."net.core.rmem_max""#;
+        let loc = parse_location(name);
+        assert_eq!(loc.len(), 4);
+        assert_eq!(loc[3], Attr("net.core.rmem_max".to_string()));
+        assert_eq!(format_location(&loc), name);
+
+        let loc = parse_location("programs.ssh.knownHosts.<name?>.hostNames");
+        assert_eq!(loc[3], Placeholder("name?".to_string()));
+        assert!(loc[3].is_placeholder());
+
+        assert!(parse_location("").is_empty());
+    }
+}
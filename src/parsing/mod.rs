@@ -1,51 +1,200 @@
 use bitcode::{Decode, Encode};
-use color_eyre::eyre::{ensure, Result};
+use color_eyre::eyre::Result;
 use html2text::from_read_with_decorator;
 use html2text::render::TrivialDecorator;
 use lazy_regex::regex_find;
 use std::borrow::Cow;
+use std::collections::HashSet;
 use tl::{HTMLTag, NodeHandle, Parser, ParserOptions, VDom};
-use tracing::{trace, warn};
+use tracing::{error, trace, warn};
+
+mod builtins;
+mod lib_functions;
+mod location;
+mod nix_modules;
+mod option_type;
+mod options_json;
+mod packages_json;
+mod rich_text;
+pub(crate) use builtins::{parse_builtins, FunText};
+pub(crate) use lib_functions::parse_lib_functions;
+pub(crate) use location::{format_location, parse_location, LocSegment};
+pub(crate) use nix_modules::{nix_module_files, parse_nix_module, parse_nix_modules};
+pub(crate) use option_type::{FlatOptType, OptType};
+pub(crate) use options_json::parse_options_json;
+pub(crate) use packages_json::{parse_packages_json, PkgText};
+pub(crate) use rich_text::{Block, RichText, Span, SpanKind};
+
+/// A single searchable item of a source
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Entry {
+    Opt(OptText),
+    Pkg(PkgText),
+    Fun(FunText),
+}
+
+impl Entry {
+    /// The text that is matched against the search pattern
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            Entry::Opt(opt) => &opt.name,
+            Entry::Pkg(pkg) => &pkg.attr_name,
+            Entry::Fun(fun) => &fun.name,
+        }
+    }
+
+    /// Links to the source code defining this entry
+    pub(crate) fn declared_by_urls(&self) -> &[String] {
+        match self {
+            Entry::Opt(opt) => &opt.declared_by_urls,
+            Entry::Pkg(pkg) => pkg.position_url.as_slice(),
+            Entry::Fun(fun) => &fun.declared_by_urls,
+        }
+    }
+}
 
 /// A fully parsed option entity with fields formatted as raw text ready to print
 #[derive(Clone, Debug, Encode, Decode, PartialEq)]
 pub(crate) struct OptText {
     pub(crate) id: String,
     pub(crate) name: String,
-    pub(crate) description: String,
+    /// The attribute path of the option, i.e. `name` split into its segments
+    pub(crate) loc: Vec<LocSegment>,
     pub(crate) var_type: String,
+    /// The structure of `var_type`, if it is a module system type
+    pub(crate) type_model: FlatOptType,
     pub(crate) default: String,
-    pub(crate) example: String,
+    /// The description and example with their formatting. [`RichText::to_plain`] gives them as plain text.
+    pub(crate) rich_description: RichText,
+    pub(crate) rich_example: RichText,
     pub(crate) declared_by: String,
     pub(crate) declared_by_urls: Vec<String>,
 }
 
+impl OptText {
+    pub(crate) fn opt_type(&self) -> Option<OptType> {
+        self.type_model.to_type()
+    }
+}
+
 /// Structure of data/index.html (nix-darwin): Each option header is in a `<dt>`, associated description, type, default, example and link to docs is in a `<dd>`.
-/// This method assumes that there's an equal number of `<dt>` and `<dd>` tags, and that they come paired up one after the other. If the number of `<dt>` and `<dd>` tags don't match, this panics. If they are out of order, we have no way of catching it, so the output will just be meaningless.
-pub(crate) fn parse_options(html: &str) -> Result<Vec<OptText>> {
+/// Terms and descriptions are paired by adjacency within each `<dl>`, so a term without a description (or the reverse) only drops that entry instead of the whole source. Lists nested in descriptions are part of their option, not options themselves.
+pub(crate) fn parse_options(html: &str) -> Result<(Vec<OptText>, ParseReport)> {
     let dom = tl::parse(html, ParserOptions::default())?;
     let p = dom.parser();
-    // TODO: To parse the Nixpkgs reference manual ("https://nixos.org/manual/nixpkgs/stable/"), would it help to pull out dl lists first and then parse dt/dd tags pairwise in each list?
-    let dt_tags = dom
-        .query_selector("dt")
-        .expect("dt is a valid CSS selector")
-        .collect::<Vec<_>>();
-    let dd_tags = dom
-        .query_selector("dd")
-        .expect("dd is a valid CSS selector")
-        .collect::<Vec<_>>();
-
-    ensure!(
-        dt_tags.len() == dd_tags.len(),
-        "Should have {} dt tags = {} dd tags",
-        dt_tags.len(),
-        dd_tags.len()
-    );
-
-    Ok(std::iter::zip(dt_tags, dd_tags)
-        .filter_map(|(dt, dd)| OptParser::new(dt, dd, p).parse())
-        .map(std::convert::Into::into)
-        .collect())
+
+    let mut opts = vec![];
+    let mut report = ParseReport::default();
+    let mut nested = HashSet::new();
+    for dl in dom
+        .query_selector("dl")
+        .expect("dl is a valid CSS selector")
+    {
+        if nested.contains(&dl.get_inner()) {
+            continue;
+        }
+        let Some(dl) = dl.get(p).and_then(tl::Node::as_tag) else {
+            continue;
+        };
+        let mut term: Option<NodeHandle> = None;
+        for &child in dl.children().top().iter() {
+            let Some(tag) = child.get(p).and_then(tl::Node::as_tag) else {
+                continue;
+            };
+            match tag.name().as_utf8_str().as_ref() {
+                "dt" => {
+                    if let Some(dt) = term.replace(child) {
+                        report.skip(entry_label(dt, p), "term without a description");
+                    }
+                }
+                "dd" => {
+                    nested.extend(descendant_lists(tag, p));
+                    let Some(dt) = term.take() else {
+                        report.skip(entry_label(child, p), "description without a term");
+                        continue;
+                    };
+                    match OptParser::new(dt, child, p).parse() {
+                        Some(data) if !data.term.is_empty() => {
+                            opts.push(OptText::from(data));
+                            report.entries += 1;
+                        }
+                        _ => report.skip(entry_label(dt, p), "malformed description"),
+                    }
+                }
+                _ => (),
+            }
+        }
+        if let Some(dt) = term {
+            report.skip(entry_label(dt, p), "term without a description");
+        }
+    }
+    Ok((opts, report))
+}
+
+/// The `<dl>` elements inside `tag`
+fn descendant_lists(tag: &HTMLTag, p: &Parser) -> Vec<u32> {
+    tag.query_selector(p, "dl")
+        .into_iter()
+        .flatten()
+        .map(|nh| nh.get_inner())
+        .collect()
+}
+
+/// Identifies a skipped entry by the anchor of its term, or the start of its text
+fn entry_label(handle: NodeHandle, p: &Parser) -> String {
+    let Some(tag) = handle.get(p).and_then(tl::Node::as_tag) else {
+        return String::new();
+    };
+    let anchor = tag
+        .query_selector(p, "a[id]")
+        .and_then(|mut q| q.next())
+        .and_then(|nh| {
+            nh.get(p)?
+                .as_tag()?
+                .attributes()
+                .id()
+                .map(|id| id.as_utf8_str().to_string())
+        });
+    anchor.unwrap_or_else(|| {
+        let text = tag.inner_text(p);
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        match text.char_indices().nth(60) {
+            Some((end, _)) => format!("{}…", &text[..end]),
+            None => text,
+        }
+    })
+}
+
+/// How many entries of a source were parsed, and which ones were skipped because they were malformed
+#[derive(Clone, Debug, Default, Encode, Decode, PartialEq)]
+pub(crate) struct ParseReport {
+    pub(crate) entries: usize,
+    pub(crate) skipped: Vec<SkippedEntry>,
+}
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq)]
+pub(crate) struct SkippedEntry {
+    /// The anchor id or the start of the text of the entry
+    pub(crate) label: String,
+    pub(crate) reason: String,
+}
+
+impl ParseReport {
+    /// A report for a source in which nothing was skipped
+    pub(crate) fn complete(entries: usize) -> Self {
+        Self {
+            entries,
+            skipped: vec![],
+        }
+    }
+
+    fn skip(&mut self, label: String, reason: &str) {
+        error!(label, reason, "Skipped malformed entry");
+        self.skipped.push(SkippedEntry {
+            label,
+            reason: reason.to_string(),
+        });
+    }
 }
 
 /// Different data sources expose version information in different ways, so we try multiple methods in hopes of eventually succeeding.
@@ -188,18 +337,18 @@ impl From<OptData<'_>> for OptRawHTML {
 impl From<OptRawHTML> for OptText {
     fn from(html: OptRawHTML) -> Self {
         let name = read_html_strip_prefix(&html.name, None);
-        let description = read_html_strip_prefix(&html.description, None);
         let var_type = read_html_strip_prefix(&html.var_type, Some("Type:"));
         let default = read_html_strip_prefix(&html.default, Some("Default:"));
-        let example = read_html_strip_prefix(&html.example, Some("Example:"));
         let declared_by = read_html_strip_prefix(&html.declared_by, Some("Declared By:"));
         Self {
             id: html.id,
+            loc: parse_location(&name),
             name,
-            description,
+            type_model: FlatOptType::parse(&var_type),
             var_type,
             default,
-            example,
+            rich_description: RichText::from_html(&html.description),
+            rich_example: RichText::from_html(&html.example).strip_prefix("Example:"),
             declared_by,
             declared_by_urls: html.declared_by_urls,
         }
@@ -236,10 +385,10 @@ impl std::fmt::Display for OptText {
             f,
             "Name: {}\nDescription: {}\n{}\n{}\n{}\n{}\n{:?}\n--------------",
             self.name,
-            self.description,
+            self.rich_description.to_plain(),
             self.var_type,
             self.default,
-            self.example,
+            self.rich_example.to_plain(),
             self.declared_by,
             self.declared_by_urls
         )
@@ -369,3 +518,53 @@ impl<'dom> OptParser<'dom> {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn option_html(name: &str) -> String {
+        format!(
+            r##"<dt><span class="term"><a id="opt-{name}"></a><a class="term" href="#opt-{name}"><code class="option">{name}</code></a></span></dt>
+<dd><p>Whether to enable {name}.</p>
+<p><span class="emphasis"><em>Type:</em></span> boolean</p>
+<p><span class="emphasis"><em>Default:</em></span> <code class="literal">false</code></p></dd>"##
+        )
+    }
+
+    #[test]
+    fn malformed_entries_are_skipped() -> Result<()> {
+        let html = format!(
+            r#"<html><body><dl class="variablelist">
+{}
+<dt><span class="term"><a id="opt-stray"></a><code class="option">stray</code></span></dt>
+{}
+<dd><p>A description without a term</p></dd>
+<dt><span class="term"><a id="opt-nested"></a><code class="option">nested</code></span></dt>
+<dd><p>Contains a list:</p><dl><dt>inner term</dt><dd>inner description</dd></dl>
+<p><span class="emphasis"><em>Type:</em></span> string</p></dd>
+</dl></body></html>"#,
+            option_html("services.foo.enable"),
+            option_html("services.bar.enable"),
+        );
+        let (opts, report) = parse_options(&html)?;
+        assert_eq!(
+            opts.iter().map(|o| o.name.as_str()).collect::<Vec<_>>(),
+            vec!["services.foo.enable", "services.bar.enable", "nested"]
+        );
+        assert_eq!(opts[1].var_type, "boolean");
+        assert_eq!(report.entries, 3);
+        assert_eq!(
+            report
+                .skipped
+                .iter()
+                .map(|s| (s.label.as_str(), s.reason.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("opt-stray", "term without a description"),
+                ("A description without a term", "description without a term"),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,839 @@
+use super::{format_location, FlatOptType, LocSegment, OptText, OptType, RichText};
+use color_eyre::eyre::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// All `.nix` files in `path`, or `path` itself if it is a file.
+/// Hidden directories and symlinks (e.g. `result` links into the Nix store) are skipped.
+pub(crate) fn nix_module_files(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = vec![];
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let path = entry.path();
+            if file_type.is_symlink() || entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e == "nix") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Statically extract the options declared in the `.nix` files at `path`, which is either a module file or a directory of modules.
+pub(crate) fn parse_nix_modules(path: &Path) -> Result<Vec<OptText>> {
+    let mut opts = vec![];
+    for file in nix_module_files(path)? {
+        let src = std::fs::read_to_string(&file)?;
+        let file = std::fs::canonicalize(&file).unwrap_or(file);
+        opts.extend(parse_nix_module(&src, &file));
+    }
+    Ok(opts)
+}
+
+/// Find every `mkOption`, `mkEnableOption` and `mkPackageOption` call that is bound to an attribute, and extract the literal text of its `type`, `default`, `example` and `description`.
+/// This is a best effort without evaluating Nix: attribute paths are followed through nested attribute sets (dropping the leading `options`), but not through `let` bindings or function calls.
+pub(crate) fn parse_nix_module(src: &str, file: &Path) -> Vec<OptText> {
+    Scanner {
+        src,
+        toks: &tokenize(src),
+        file,
+    }
+    .scan()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    /// The contents of a string, with escapes resolved and interpolations kept as written
+    Str(String),
+    Punct(char),
+    Other,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    start: usize,
+    end: usize,
+    line: usize,
+}
+
+impl Token {
+    fn is(&self, c: char) -> bool {
+        self.tok == Tok::Punct(c)
+    }
+
+    fn ident(&self) -> Option<&str> {
+        match &self.tok {
+            Tok::Ident(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+fn tokenize(src: &str) -> Vec<Token> {
+    let b = src.as_bytes();
+    let mut toks = vec![];
+    let mut i = 0;
+    let mut line = 1;
+    while i < b.len() {
+        let start = i;
+        let start_line = line;
+        let tok = match b[i] {
+            b'\n' => {
+                line += 1;
+                i += 1;
+                continue;
+            }
+            c if c.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b'#' => {
+                while i < b.len() && b[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            b'/' if b.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i < b.len() && !(b[i] == b'*' && b.get(i + 1) == Some(&b'/')) {
+                    line += usize::from(b[i] == b'\n');
+                    i += 1;
+                }
+                i += 2;
+                continue;
+            }
+            b'"' => {
+                let content;
+                (content, i) = lex_string(src, i + 1, &mut line);
+                Tok::Str(content)
+            }
+            b'\'' if b.get(i + 1) == Some(&b'\'') => {
+                let content;
+                (content, i) = lex_indented_string(src, i + 2, &mut line);
+                Tok::Str(dedent(&content))
+            }
+            c if c.is_ascii_alphabetic() || c == b'_' => {
+                while i < b.len() && (b[i].is_ascii_alphanumeric() || b"_'-".contains(&b[i])) {
+                    i += 1;
+                }
+                Tok::Ident(src[start..i].to_string())
+            }
+            // Paths like ./foo.nix, ../foo and /etc/nixos
+            b'.' | b'/' if is_path_start(&b[i..]) => {
+                while i < b.len() && (b[i].is_ascii_alphanumeric() || b"._-+/".contains(&b[i])) {
+                    i += 1;
+                }
+                Tok::Other
+            }
+            b'.' if src[i..].starts_with("...") => {
+                i += 3;
+                Tok::Other
+            }
+            b'=' | b'!' | b'<' | b'>' if b.get(i + 1) == Some(&b'=') => {
+                i += 2;
+                Tok::Other
+            }
+            c if b"{}[]()=;.:,?@".contains(&c) => {
+                i += 1;
+                Tok::Punct(char::from(c))
+            }
+            c if c.is_ascii_digit() => {
+                while i < b.len() && (b[i].is_ascii_alphanumeric() || b[i] == b'.') {
+                    i += 1;
+                }
+                Tok::Other
+            }
+            _ => {
+                i += src[i..].chars().next().map_or(1, char::len_utf8);
+                Tok::Other
+            }
+        };
+        toks.push(Token {
+            tok,
+            start,
+            end: i.min(src.len()),
+            line: start_line,
+        });
+    }
+    toks
+}
+
+fn is_path_start(b: &[u8]) -> bool {
+    b.starts_with(b"./")
+        || b.starts_with(b"../")
+        || (b[0] == b'/' && b.get(1).is_some_and(u8::is_ascii_alphabetic))
+}
+
+/// Copy the interpolation `${...}` starting at `i` verbatim, returning the index after it.
+/// Strings nested in it are skipped as a whole, so that their quotes and braces don't end it early.
+fn lex_interpolation(src: &str, i: usize, out: &mut String, line: &mut usize) -> usize {
+    let b = src.as_bytes();
+    let mut depth = 1;
+    let mut end = i + 2;
+    while end < b.len() && depth > 0 {
+        match b[end] {
+            b'{' => depth += 1,
+            b'}' => depth -= 1,
+            b'\n' => *line += 1,
+            b'"' => {
+                end = lex_string(src, end + 1, line).1;
+                continue;
+            }
+            b'\'' if b.get(end + 1) == Some(&b'\'') => {
+                end = lex_indented_string(src, end + 2, line).1;
+                continue;
+            }
+            _ => (),
+        }
+        end += 1;
+    }
+    out.push_str(&src[i..end]);
+    end
+}
+
+/// Lex the contents of a `"double quoted"` string starting at `i`, returning the contents and the index after the closing quote.
+fn lex_string(src: &str, mut i: usize, line: &mut usize) -> (String, usize) {
+    let mut out = String::new();
+    while let Some(c) = src[i..].chars().next() {
+        match c {
+            '"' => return (out, i + 1),
+            '\\' => {
+                i += 1;
+                let Some(escaped) = src[i..].chars().next() else {
+                    break;
+                };
+                out.push(match escaped {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    c => c,
+                });
+                *line += usize::from(escaped == '\n');
+                i += escaped.len_utf8();
+            }
+            '$' if src[i..].starts_with("${") => i = lex_interpolation(src, i, &mut out, line),
+            c => {
+                *line += usize::from(c == '\n');
+                out.push(c);
+                i += c.len_utf8();
+            }
+        }
+    }
+    (out, src.len())
+}
+
+/// Lex the contents of an `''indented''` string starting at `i`, returning the contents and the index after the closing quotes.
+fn lex_indented_string(src: &str, mut i: usize, line: &mut usize) -> (String, usize) {
+    let mut out = String::new();
+    while let Some(c) = src[i..].chars().next() {
+        let rest = &src[i..];
+        if rest.starts_with("'''") {
+            out.push_str("''");
+            i += 3;
+        } else if rest.starts_with("''$") {
+            out.push('$');
+            i += 3;
+        } else if rest.starts_with("''\\") {
+            i += 3;
+            let Some(escaped) = src[i..].chars().next() else {
+                break;
+            };
+            out.push(match escaped {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                c => c,
+            });
+            i += escaped.len_utf8();
+        } else if rest.starts_with("''") {
+            return (out, i + 2);
+        } else if rest.starts_with("${") {
+            i = lex_interpolation(src, i, &mut out, line);
+        } else {
+            *line += usize::from(c == '\n');
+            out.push(c);
+            i += c.len_utf8();
+        }
+    }
+    (out, src.len())
+}
+
+/// Strip the indentation common to all non-empty lines, like Nix does for indented strings.
+fn dedent(s: &str) -> String {
+    let s = s.strip_prefix('\n').unwrap_or(s);
+    let indent = s
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start_matches(' ').len())
+        .min()
+        .unwrap_or(0);
+    s.lines()
+        .map(|l| l.get(indent..).unwrap_or(l.trim_start()))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_end()
+        .to_string()
+}
+
+/// An attribute set (or list, or parenthesized expression) that the scanner is inside of.
+#[derive(Debug)]
+struct Frame {
+    /// The attribute path this set is bound to, relative to the enclosing frame
+    path: Vec<String>,
+    /// Set for the argument of an option declaration, whose `type` may contain submodules declaring further options.
+    /// The path is then the absolute path of the option, including a `<name>` or `*` placeholder if the submodule is in an `attrsOf` or `listOf`.
+    option_path: Option<Vec<String>>,
+}
+
+struct Scanner<'a> {
+    src: &'a str,
+    toks: &'a [Token],
+    file: &'a Path,
+}
+
+impl Scanner<'_> {
+    const WRAPPERS: [&'static str; 7] = [
+        "lib",
+        "literalExpression",
+        "literalExample",
+        "literalMD",
+        "mdDoc",
+        "doc",
+        "options",
+    ];
+
+    fn scan(&self) -> Vec<OptText> {
+        let mut opts = vec![];
+        let mut stack: Vec<Frame> = vec![];
+        // Opening braces of `mkOption` arguments, with the absolute path of the option
+        let mut option_braces: HashMap<usize, Vec<String>> = HashMap::new();
+
+        for (i, tok) in self.toks.iter().enumerate() {
+            match &tok.tok {
+                Tok::Punct('{' | '[' | '(') => {
+                    let option_path = option_braces.remove(&i);
+                    let path = if tok.is('{') && option_path.is_none() {
+                        self.binding_before(i).unwrap_or_default()
+                    } else {
+                        vec![]
+                    };
+                    stack.push(Frame { path, option_path });
+                }
+                Tok::Punct('}' | ']' | ')') => {
+                    stack.pop();
+                }
+                Tok::Ident(ident) => {
+                    let Some(kind) = ["mkOption", "mkEnableOption", "mkPackageOption"]
+                        .into_iter()
+                        .find(|k| k == ident)
+                    else {
+                        continue;
+                    };
+                    let Some((binding, line)) = self.call_binding(i) else {
+                        continue;
+                    };
+                    let path = Self::absolute_path(&stack, binding);
+                    let loc = path.iter().map(|s| LocSegment::from(s.as_str())).collect();
+                    let opt = match kind {
+                        "mkOption" => {
+                            let Some(fields) = self.set_bindings(i + 1) else {
+                                continue;
+                            };
+                            let var_type =
+                                fields.get("type").map(|e| self.raw(e)).unwrap_or_default();
+                            let mut option_path = path.clone();
+                            // The outermost of the type constructors names the submodule's attribute, e.g. `attrsOf (listOf (submodule ...))`
+                            let placeholder = [("listOf", "*"), ("attrsOf", "<name>")]
+                                .into_iter()
+                                .filter_map(|(ty, p)| Some((var_type.find(ty)?, p)))
+                                .min();
+                            if let Some((_, placeholder)) = placeholder {
+                                option_path.push(placeholder.to_string());
+                            }
+                            option_braces.insert(i + 1, option_path);
+                            let var_type = compact_whitespace(&var_type);
+                            OptText {
+                                // Types of `mkOption` are Nix expressions like `types.listOf types.str` rather than the descriptions `FlatOptType::parse` reads
+                                type_model: FlatOptType::from(&OptType::Other(var_type.clone())),
+                                ..self.opt_text(
+                                    loc,
+                                    line,
+                                    fields.get("description").map(|e| self.text(e)),
+                                    var_type,
+                                    fields
+                                        .get("defaultText")
+                                        .or(fields.get("default"))
+                                        .map(|e| self.value(e)),
+                                    fields.get("example").map(|e| self.value(e)),
+                                )
+                            }
+                        }
+                        "mkEnableOption" => {
+                            let what = self
+                                .toks
+                                .get(i + 1..)
+                                .and_then(Self::first_string)
+                                .unwrap_or_default();
+                            self.opt_text(
+                                loc,
+                                line,
+                                Some(format!("Whether to enable {what}.")),
+                                "boolean".to_string(),
+                                Some("false".to_string()),
+                                Some("true".to_string()),
+                            )
+                        }
+                        _ => self.package_option(i, loc, line),
+                    };
+                    opts.push(opt);
+                }
+                _ => (),
+            }
+        }
+        opts
+    }
+
+    fn opt_text(
+        &self,
+        loc: Vec<LocSegment>,
+        line: usize,
+        description: Option<String>,
+        var_type: String,
+        default: Option<String>,
+        example: Option<String>,
+    ) -> OptText {
+        let name = format_location(&loc);
+        OptText {
+            id: format!("opt-{name}"),
+            name,
+            loc,
+            rich_description: RichText::from_markdown(&description.unwrap_or_default()),
+            // The types of `mkEnableOption` and `mkPackageOption` are given as descriptions, like in the manual
+            type_model: FlatOptType::parse(&var_type),
+            var_type,
+            default: default.unwrap_or_default(),
+            rich_example: RichText::code(&example.unwrap_or_default()),
+            declared_by: format!("{}:{line}", self.file.display()),
+            declared_by_urls: vec![file_url(self.file, line)],
+        }
+    }
+
+    /// `mkPackageOption pkgs "name" { default = ...; example = ...; }`, where the name may also be a list forming an attribute path.
+    fn package_option(&self, i: usize, loc: Vec<LocSegment>, line: usize) -> OptText {
+        // Skip the package set argument, e.g. `pkgs` or `pkgs.python3Packages`
+        let mut j = i + 1;
+        while self
+            .toks
+            .get(j)
+            .is_some_and(|t| t.ident().is_some() || t.is('.'))
+        {
+            j += 1;
+        }
+        let package = match self.toks.get(j).map(|t| &t.tok) {
+            Some(Tok::Str(s)) => {
+                j += 1;
+                s.clone()
+            }
+            Some(Tok::Punct('[')) => {
+                let mut parts = vec![];
+                j += 1;
+                while let Some(Tok::Str(s)) = self.toks.get(j).map(|t| &t.tok) {
+                    parts.push(s.clone());
+                    j += 1;
+                }
+                j += 1;
+                parts.join(".")
+            }
+            _ => String::new(),
+        };
+        let fields = self
+            .toks
+            .get(j)
+            .filter(|t| t.is('{'))
+            .and_then(|_| self.set_bindings(j))
+            .unwrap_or_default();
+        let default = match fields.get("default") {
+            Some(&(start, end)) => match &self.toks[start..end] {
+                [t] if t.tok == Tok::Ident("null".to_string()) => "null".to_string(),
+                toks => {
+                    let parts = toks
+                        .iter()
+                        .filter_map(|t| match &t.tok {
+                            Tok::Str(s) => Some(s.as_str()),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>();
+                    format!("pkgs.{}", parts.join("."))
+                }
+            },
+            None => format!("pkgs.{package}"),
+        };
+        self.opt_text(
+            loc,
+            line,
+            Some(format!("The {package} package to use.")),
+            "package".to_string(),
+            Some(default),
+            fields.get("example").map(|e| self.value(e)),
+        )
+    }
+
+    /// The attribute path of a call at `i` that is bound to an attribute, like `foo.bar = lib.mkOption`, and the line it is on.
+    fn call_binding(&self, i: usize) -> Option<(Vec<String>, usize)> {
+        let mut head = i;
+        // Qualified calls like `lib.mkOption`
+        while head >= 2 && self.toks[head - 1].is('.') && self.toks[head - 2].ident().is_some() {
+            head -= 2;
+        }
+        let eq = self.skip_with_back(head.checked_sub(1)?)?;
+        let path = self.binding_before(eq + 1)?;
+        let line = self.toks[eq.checked_sub(path.len() * 2 - 1)?].line;
+        Some((path, line))
+    }
+
+    /// Skip backwards over `with lib;` prefixes from `j`.
+    fn skip_with_back(&self, mut j: usize) -> Option<usize> {
+        while self.toks[j].is(';') {
+            let mut k = j.checked_sub(1)?;
+            while k > 0
+                && (self.toks[k].ident().is_some() || self.toks[k].is('.'))
+                && self.toks[k].ident() != Some("with")
+            {
+                k -= 1;
+            }
+            if self.toks[k].ident() != Some("with") {
+                return Some(j);
+            }
+            j = k.checked_sub(1)?;
+        }
+        Some(j)
+    }
+
+    /// If the token before `i` ends a binding `path =` (possibly followed by `with x;` or `rec`), return its attribute path.
+    fn binding_before(&self, i: usize) -> Option<Vec<String>> {
+        let mut eq = self.skip_with_back(i.checked_sub(1)?)?;
+        if self.toks[eq].ident() == Some("rec") {
+            eq = eq.checked_sub(1)?;
+        }
+        if !self.toks[eq].is('=') {
+            return None;
+        }
+        let mut path = vec![];
+        let mut j = eq;
+        loop {
+            j = j.checked_sub(1)?;
+            match &self.toks[j].tok {
+                Tok::Ident(s) | Tok::Str(s) => path.push(s.clone()),
+                _ => return None,
+            }
+            if j == 0 || !self.toks[j - 1].is('.') {
+                break;
+            }
+            j -= 1;
+        }
+        // A binding starts a set, or follows another binding
+        let starts_binding = j == 0
+            || self.toks[j - 1].is('{')
+            || self.toks[j - 1].is(';')
+            || self.toks[j - 1].ident() == Some("let");
+        if !starts_binding {
+            return None;
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// The absolute path of a binding inside the frames on `stack`, dropping the `options` attribute that declarations are nested in.
+    fn absolute_path(stack: &[Frame], binding: Vec<String>) -> Vec<String> {
+        let start = stack.iter().rposition(|f| f.option_path.is_some());
+        let prefix = start
+            .and_then(|s| stack[s].option_path.clone())
+            .unwrap_or_default();
+        let mut rest: Vec<String> = stack[start.map_or(0, |s| s + 1)..]
+            .iter()
+            .flat_map(|f| f.path.iter().cloned())
+            .chain(binding)
+            .collect();
+        if rest.first().is_some_and(|s| s == "options") {
+            rest.remove(0);
+        }
+        [prefix, rest].concat()
+    }
+
+    /// The top level bindings of the attribute set opened at `open`, as token ranges of their values.
+    fn set_bindings(&self, open: usize) -> Option<HashMap<String, (usize, usize)>> {
+        if !self.toks.get(open)?.is('{') {
+            return None;
+        }
+        let mut bindings = HashMap::new();
+        let mut depth = 0;
+        let mut name = None;
+        let mut value_start = None;
+        let mut binding_start = open + 1;
+        for (j, tok) in self.toks.iter().enumerate().skip(open + 1) {
+            match tok.tok {
+                Tok::Punct('{' | '[' | '(') => depth += 1,
+                Tok::Punct('}' | ']' | ')') if depth == 0 => break,
+                Tok::Punct('}' | ']' | ')') => depth -= 1,
+                Tok::Punct('=') if depth == 0 && value_start.is_none() => {
+                    name = self.toks[binding_start..j]
+                        .iter()
+                        .filter_map(|t| t.ident())
+                        .next_back()
+                        .map(str::to_string);
+                    value_start = Some(j + 1);
+                }
+                Tok::Punct(';') if depth == 0 => {
+                    if let (Some(name), Some(s)) = (name.take(), value_start.take()) {
+                        bindings.insert(name, (s, j));
+                    }
+                    binding_start = j + 1;
+                }
+                _ => (),
+            }
+        }
+        Some(bindings)
+    }
+
+    /// The source text of the tokens in `range`
+    fn raw(&self, &(s, e): &(usize, usize)) -> String {
+        match (self.toks.get(s), self.toks.get(e.wrapping_sub(1))) {
+            (Some(first), Some(last)) if s < e => {
+                self.src[first.start..last.end].trim().to_string()
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// The string literal in `range`, if it is one, possibly wrapped in e.g. `lib.mdDoc` or parentheses.
+    fn literal(&self, &(s, e): &(usize, usize)) -> Option<&str> {
+        let mut strings = self.toks[s..e]
+            .iter()
+            .filter(|t| !matches!(t.tok, Tok::Punct('(' | ')' | '.')));
+        let mut literal = None;
+        for t in strings.by_ref() {
+            match &t.tok {
+                Tok::Ident(w) if Self::WRAPPERS.contains(&w.as_str()) && literal.is_none() => (),
+                Tok::Str(s) if literal.is_none() => literal = Some(s.as_str()),
+                _ => return None,
+            }
+        }
+        literal
+    }
+
+    /// Text like descriptions, where a string literal is shown without quotes
+    fn text(&self, range: &(usize, usize)) -> String {
+        self.literal(range)
+            .map_or_else(|| self.raw(range), str::to_string)
+    }
+
+    /// Values like defaults and examples, where only `literalExpression` and friends are unwrapped, while plain strings are values that keep their quotes.
+    fn value(&self, range: &(usize, usize)) -> String {
+        let is_wrapped = self.toks[range.0..range.1]
+            .iter()
+            .any(|t| t.ident().is_some_and(|w| w.starts_with("literal")));
+        match self.literal(range) {
+            Some(s) if is_wrapped => s.to_string(),
+            _ => self.raw(range),
+        }
+    }
+
+    fn first_string(toks: &[Token]) -> Option<String> {
+        match toks.first().map(|t| &t.tok) {
+            Some(Tok::Str(s)) => Some(s.clone()),
+            Some(Tok::Punct('(')) => toks.iter().take_while(|t| !t.is(')')).find_map(|t| match &t
+                .tok
+            {
+                Tok::Str(s) => Some(s.clone()),
+                _ => None,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// A `file://` URL pointing at `line` of `path`, percent-encoding everything but unreserved characters and separators.
+fn file_url(path: &Path, line: usize) -> String {
+    let encoded = path
+        .to_string_lossy()
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+                char::from(b).to_string()
+            } else {
+                format!("%{b:02X}")
+            }
+        })
+        .collect::<String>();
+    format!("file://{encoded}#L{line}")
+}
+
+fn compact_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODULE: &str = r#"{ config, lib, pkgs, ... }:
+let
+  cfg = config.services.foo;
+in
+{
+  options.services.foo = {
+    enable = lib.mkEnableOption "the foo service";
+
+    package = lib.mkPackageOption pkgs "foo" { };
+
+    port = lib.mkOption {
+      type = lib.types.port;
+      default = 8080;
+      example = 80;
+      description = "Port to listen on; see foo --help.";
+    };
+
+    # A comment with mkOption in it
+    settings = with lib; mkOption {
+      type = types.attrsOf (types.submodule {
+        options.root = mkOption {
+          type = types.path;
+          description = ''
+            The root of the site.
+
+            Must exist.
+          '';
+        };
+      });
+      default = { };
+      defaultText = lib.literalExpression "{ }";
+      example = "main";
+      description = lib.mdDoc "Sites to serve.";
+    };
+  };
+
+  config = lib.mkIf cfg.enable { };
+}
+"#;
+
+    #[test]
+    fn parse_module_declarations() {
+        let opts = parse_nix_module(MODULE, Path::new("/src/foo.nix"));
+        let names = opts.iter().map(|o| o.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "services.foo.enable",
+                "services.foo.package",
+                "services.foo.port",
+                "services.foo.settings",
+                "services.foo.settings.<name>.root",
+            ]
+        );
+
+        let enable = &opts[0];
+        assert_eq!(enable.id, "opt-services.foo.enable");
+        assert_eq!(enable.var_type, "boolean");
+        assert_eq!(enable.opt_type(), Some(OptType::Bool));
+        assert_eq!(
+            enable.rich_description.to_plain(),
+            "Whether to enable the foo service."
+        );
+        assert_eq!(enable.declared_by, "/src/foo.nix:7");
+        assert_eq!(enable.declared_by_urls, vec!["file:///src/foo.nix#L7"]);
+
+        let package = &opts[1];
+        assert_eq!(package.var_type, "package");
+        assert_eq!(package.default, "pkgs.foo");
+
+        let port = &opts[2];
+        assert_eq!(port.var_type, "lib.types.port");
+        assert_eq!(
+            port.opt_type(),
+            Some(OptType::Other("lib.types.port".to_string()))
+        );
+        assert_eq!(port.default, "8080");
+        assert_eq!(port.rich_example.to_plain(), "80");
+        assert_eq!(
+            port.rich_description.to_plain(),
+            "Port to listen on; see foo --help."
+        );
+        assert_eq!(port.declared_by, "/src/foo.nix:11");
+
+        let settings = &opts[3];
+        assert_eq!(settings.default, "{ }");
+        assert_eq!(settings.rich_example.to_plain(), r#""main""#);
+        assert_eq!(settings.rich_description.to_plain(), "Sites to serve.");
+
+        let root = &opts[4];
+        assert!(root.loc[3].is_placeholder());
+        assert_eq!(root.var_type, "types.path");
+        assert_eq!(
+            root.rich_description.to_plain(),
+            "The root of the site.\nMust exist."
+        );
+    }
+
+    #[test]
+    fn file_urls_are_encoded() {
+        assert_eq!(
+            file_url(Path::new("/src/my modules/#1/föö.nix"), 3),
+            "file:///src/my%20modules/%231/f%C3%B6%C3%B6.nix#L3"
+        );
+    }
+
+    #[test]
+    fn parse_module_edge_cases() {
+        let src = r#"{ lib, ... }: {
+  /* Configured by José — see below */
+  options.foo = lib.mkOption {
+    type = lib.types.attrsOf (lib.types.listOf (lib.types.submodule {
+      options.bar = lib.mkEnableOption "bar";
+    }));
+    description = "Joined with ${lib.concatStringsSep ", " [ "a" "b" ]} here.";
+  };
+}
+"#;
+        let opts = parse_nix_module(src, Path::new("/src/foo.nix"));
+        let names = opts.iter().map(|o| o.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["foo", "foo.<name>.bar"]);
+        assert_eq!(
+            opts[0].rich_description.to_plain(),
+            r#"Joined with ${lib.concatStringsSep ", " [ "a" "b" ]} here."#
+        );
+        assert_eq!(opts[1].declared_by, "/src/foo.nix:5");
+    }
+
+    #[test]
+    fn parse_module_directory() -> Result<()> {
+        let tmpdir = tempfile::tempdir()?;
+        std::fs::create_dir_all(tmpdir.path().join("modules/.git"))?;
+        std::fs::write(
+            tmpdir.path().join("modules/a.nix"),
+            "{ lib, ... }: { options.a = lib.mkEnableOption \"a\"; }",
+        )?;
+        std::fs::write(
+            tmpdir.path().join("modules/.git/b.nix"),
+            "{ lib, ... }: { options.b = lib.mkEnableOption \"b\"; }",
+        )?;
+        std::fs::write(tmpdir.path().join("README.md"), "options.c = mkOption { };")?;
+
+        let opts = parse_nix_modules(tmpdir.path())?;
+        assert_eq!(opts.len(), 1);
+        assert_eq!(opts[0].name, "a");
+        Ok(())
+    }
+}
@@ -0,0 +1,334 @@
+use bitcode::{Decode, Encode};
+
+/// The structure of an option type, as far as it can be recovered from the description generated by the `lib.types` of nixpkgs, e.g. `null or (list of (submodule))`
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum OptType {
+    Bool,
+    /// Any kind of integer, e.g. also `16 bit unsigned integer; between 0 and 65535 (both inclusive)`
+    Int,
+    Float,
+    /// Any kind of string, e.g. also `strings concatenated with "\n"`
+    Str,
+    Path,
+    Package,
+    Submodule,
+    Anything,
+    /// The allowed values, as written in the description, e.g. `"a"` or `1`
+    Enum(Vec<String>),
+    ListOf(Box<OptType>),
+    AttrsOf(Box<OptType>),
+    NullOr(Box<OptType>),
+    Function(Box<OptType>),
+    /// Any one of at least two types
+    Either(Vec<OptType>),
+    /// A type that isn't modelled, with its description
+    Other(String),
+}
+
+/// The constructor of a type wrapping one other type
+type Wrap = fn(Box<OptType>) -> OptType;
+
+impl OptType {
+    /// The allowed values if this is an enum, or a nullable enum
+    pub(crate) fn choices(&self) -> Option<&[String]> {
+        match self {
+            Self::Enum(values) => Some(values),
+            Self::NullOr(inner) => inner.choices(),
+            _ => None,
+        }
+    }
+
+    /// Parse a type description. Descriptions that aren't recognized become [`OptType::Other`].
+    pub(crate) fn parse(description: &str) -> Self {
+        let description = description.trim();
+        let description = description
+            .strip_suffix("(read only)")
+            .map_or(description, str::trim_end);
+        Self::parse_expr(description)
+    }
+
+    fn parse_expr(s: &str) -> Self {
+        let s = strip_parens(s.trim());
+
+        // Qualifications like `string, not containing newlines or colons` can also contain " or ", so only split if each part is a type
+        let alternatives = split_top_level(s, " or ");
+        if alternatives.len() > 1 {
+            let types = alternatives
+                .iter()
+                .filter(|alt| strip_parens(alt.trim()) != "null")
+                .map(|alt| Self::parse_expr(alt))
+                .collect::<Vec<_>>();
+            if !types.iter().any(|t| matches!(t, Self::Other(_))) {
+                let nullable = types.len() < alternatives.len();
+                let mut types = types
+                    .into_iter()
+                    .flat_map(|t| match t {
+                        Self::Either(nested) => nested,
+                        t => vec![t],
+                    })
+                    .collect::<Vec<_>>();
+                let t = match types.len() {
+                    0 => Self::Other("null".to_string()),
+                    1 => types.remove(0),
+                    _ => Self::Either(types),
+                };
+                return if nullable {
+                    Self::NullOr(Box::new(t))
+                } else {
+                    t
+                };
+            }
+        }
+
+        let wrappers: [(&str, Wrap); 5] = [
+            ("list of ", Self::ListOf),
+            ("non-empty list of ", Self::ListOf),
+            ("attribute set of ", Self::AttrsOf),
+            ("lazy attribute set of ", Self::AttrsOf),
+            ("function that evaluates to a(n) ", Self::Function),
+        ];
+        for (prefix, wrap) in wrappers {
+            if let Some(inner) = s.strip_prefix(prefix) {
+                return wrap(Box::new(Self::parse_expr(inner)));
+            }
+        }
+        if let Some(values) = s.strip_prefix("one of ") {
+            return Self::Enum(
+                split_top_level(values, ",")
+                    .into_iter()
+                    .map(|v| v.trim().to_string())
+                    .collect(),
+            );
+        }
+        if let Some(value) = s
+            .strip_prefix("value ")
+            .and_then(|v| v.strip_suffix(" (singular enum)"))
+        {
+            return Self::Enum(vec![value.to_string()]);
+        }
+
+        match s {
+            "boolean" => Self::Bool,
+            "package" => Self::Package,
+            "submodule" => Self::Submodule,
+            "anything" | "raw value" | "unspecified value" => Self::Anything,
+            "attribute set" => Self::AttrsOf(Box::new(Self::Anything)),
+            "lines" => Self::Str,
+            s if s.contains("integer") => Self::Int,
+            s if s.starts_with("floating point number") => Self::Float,
+            s if [
+                "string",
+                "non-empty string",
+                "strings concatenated",
+                "Concatenated string",
+            ]
+            .iter()
+            .any(|p| s.starts_with(p)) =>
+            {
+                Self::Str
+            }
+            s if s.starts_with("path") || s.starts_with("absolute path") => Self::Path,
+            s => Self::Other(s.to_string()),
+        }
+    }
+
+    fn flatten_into(&self, nodes: &mut Vec<TypeNode>) {
+        let (node, children) = match self {
+            Self::Bool => (TypeNode::Bool, &[][..]),
+            Self::Int => (TypeNode::Int, &[][..]),
+            Self::Float => (TypeNode::Float, &[][..]),
+            Self::Str => (TypeNode::Str, &[][..]),
+            Self::Path => (TypeNode::Path, &[][..]),
+            Self::Package => (TypeNode::Package, &[][..]),
+            Self::Submodule => (TypeNode::Submodule, &[][..]),
+            Self::Anything => (TypeNode::Anything, &[][..]),
+            Self::Enum(values) => (TypeNode::Enum(values.clone()), &[][..]),
+            Self::Other(description) => (TypeNode::Other(description.clone()), &[][..]),
+            Self::ListOf(inner) => (TypeNode::ListOf, std::slice::from_ref(&**inner)),
+            Self::AttrsOf(inner) => (TypeNode::AttrsOf, std::slice::from_ref(&**inner)),
+            Self::NullOr(inner) => (TypeNode::NullOr, std::slice::from_ref(&**inner)),
+            Self::Function(inner) => (TypeNode::Function, std::slice::from_ref(&**inner)),
+            Self::Either(types) => (TypeNode::Either(types.len()), types.as_slice()),
+        };
+        nodes.push(node);
+        for child in children {
+            child.flatten_into(nodes);
+        }
+    }
+
+    fn unflatten(nodes: &mut impl Iterator<Item = TypeNode>) -> Option<Self> {
+        Some(match nodes.next()? {
+            TypeNode::Bool => Self::Bool,
+            TypeNode::Int => Self::Int,
+            TypeNode::Float => Self::Float,
+            TypeNode::Str => Self::Str,
+            TypeNode::Path => Self::Path,
+            TypeNode::Package => Self::Package,
+            TypeNode::Submodule => Self::Submodule,
+            TypeNode::Anything => Self::Anything,
+            TypeNode::Enum(values) => Self::Enum(values),
+            TypeNode::Other(description) => Self::Other(description),
+            TypeNode::ListOf => Self::ListOf(Box::new(Self::unflatten(nodes)?)),
+            TypeNode::AttrsOf => Self::AttrsOf(Box::new(Self::unflatten(nodes)?)),
+            TypeNode::NullOr => Self::NullOr(Box::new(Self::unflatten(nodes)?)),
+            TypeNode::Function => Self::Function(Box::new(Self::unflatten(nodes)?)),
+            TypeNode::Either(n) => Self::Either(
+                (0..n)
+                    .map(|_| Self::unflatten(nodes))
+                    .collect::<Option<_>>()?,
+            ),
+        })
+    }
+}
+
+/// An [`OptType`] in the form it is cached in: The nodes of the type tree in prefix order, because the cache format does not support recursive types.
+#[derive(Clone, Debug, Default, Encode, Decode, PartialEq)]
+pub(crate) struct FlatOptType(Vec<TypeNode>);
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq)]
+enum TypeNode {
+    Bool,
+    Int,
+    Float,
+    Str,
+    Path,
+    Package,
+    Submodule,
+    Anything,
+    Enum(Vec<String>),
+    Other(String),
+    /// Followed by the element type
+    ListOf,
+    AttrsOf,
+    NullOr,
+    Function,
+    /// Followed by this many alternatives
+    Either(usize),
+}
+
+impl From<&OptType> for FlatOptType {
+    fn from(t: &OptType) -> Self {
+        let mut nodes = vec![];
+        t.flatten_into(&mut nodes);
+        Self(nodes)
+    }
+}
+
+impl FlatOptType {
+    pub(crate) fn parse(description: &str) -> Self {
+        Self::from(&OptType::parse(description))
+    }
+
+    /// The type tree, or `None` for options without a type
+    pub(crate) fn to_type(&self) -> Option<OptType> {
+        OptType::unflatten(&mut self.0.iter().cloned())
+    }
+}
+
+/// Remove parentheses around all of `s`, but not from e.g. `(a) or (b)`
+fn strip_parens(mut s: &str) -> &str {
+    while s.starts_with('(') && closing_paren(s) == Some(s.len() - 1) {
+        s = s[1..s.len() - 1].trim();
+    }
+    s
+}
+
+/// The index of the parenthesis closing the one that `s` starts with
+fn closing_paren(s: &str) -> Option<usize> {
+    let mut depth = 0_usize;
+    let mut quoted = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Split `s` at each `sep` that is neither in parentheses nor in a string
+fn split_top_level<'a>(s: &'a str, sep: &str) -> Vec<&'a str> {
+    let mut parts = vec![];
+    let mut depth = 0_usize;
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth = depth.saturating_sub(1),
+            _ if !quoted && depth == 0 && i >= start && s[i..].starts_with(sep) => {
+                parts.push(&s[start..i]);
+                start = i + sep.len();
+            }
+            _ => (),
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OptType::*;
+    use super::*;
+
+    #[test]
+    fn parse_type_descriptions() {
+        let cases = [
+            ("boolean", Bool),
+            (
+                "16 bit unsigned integer; between 0 and 65535 (both inclusive)",
+                Int,
+            ),
+            ("string, not containing newlines or colons", Str),
+            ("package (read only)", Package),
+            (
+                "null or (list of (submodule))",
+                NullOr(Box::new(ListOf(Box::new(Submodule)))),
+            ),
+            (
+                "attribute set of (string or signed integer or boolean)",
+                AttrsOf(Box::new(Either(vec![Str, Int, Bool]))),
+            ),
+            (
+                r#"null or one of "a", "b, c", 3"#,
+                NullOr(Box::new(Enum(vec![
+                    r#""a""#.to_string(),
+                    r#""b, c""#.to_string(),
+                    "3".to_string(),
+                ]))),
+            ),
+            ("(path) or (package)", Either(vec![Path, Package])),
+            (
+                "Nix configuration value",
+                Other("Nix configuration value".to_string()),
+            ),
+        ];
+        for (description, expected) in cases {
+            assert_eq!(OptType::parse(description), expected, "{description}");
+        }
+    }
+
+    #[test]
+    fn flat_type_roundtrip() {
+        let t = OptType::parse("null or (attribute set of (list of (string or path)))");
+        let flat = FlatOptType::from(&t);
+        assert_eq!(flat.to_type(), Some(t));
+        assert_eq!(FlatOptType::default().to_type(), None);
+        assert_eq!(
+            OptType::parse(r#"one of "x", "y""#).choices(),
+            Some([r#""x""#.to_string(), r#""y""#.to_string()].as_slice())
+        );
+    }
+}
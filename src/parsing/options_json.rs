@@ -0,0 +1,244 @@
+use super::{parse_location, FlatOptType, LocSegment, OptText, RichText};
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// An option as it appears in the `options.json` file produced by the nixpkgs documentation build (`nixos-render-docs` and friends).
+/// Only the fields that nox displays are deserialized.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonOpt {
+    #[serde(default)]
+    declarations: Vec<JsonDeclaration>,
+    #[serde(default)]
+    default: Option<JsonLiteral>,
+    #[serde(default)]
+    description: Option<JsonLiteral>,
+    #[serde(default)]
+    example: Option<JsonLiteral>,
+    /// The segments of the option name, with placeholders like `<name>` and `*`
+    #[serde(default)]
+    loc: Vec<String>,
+    #[serde(default)]
+    read_only: bool,
+    #[serde(default, rename = "type")]
+    var_type: String,
+}
+
+/// NixOS emits declarations as paths relative to the nixpkgs root (or absolute store paths for out-of-tree modules), while e.g. Home Manager emits attribute sets with a display name and a url.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum JsonDeclaration {
+    Named { name: String, url: Option<String> },
+    Path(String),
+}
+
+impl JsonDeclaration {
+    fn name(&self) -> &str {
+        match self {
+            Self::Named { name, .. } | Self::Path(name) => name,
+        }
+    }
+
+    fn url(&self) -> Option<String> {
+        match self {
+            Self::Named { url, .. } => url.clone(),
+            Self::Path(p) if p.starts_with("https://") || p.starts_with("http://") => {
+                Some(p.clone())
+            }
+            Self::Path(p) if p.starts_with('/') => Some(format!("file://{p}")),
+            Self::Path(_) => None,
+        }
+    }
+}
+
+/// Defaults, examples and descriptions are either wrapped in `{ _type = "literalExpression"; text = ...; }` (or `literalMD`, `mdDoc`), or are plain JSON values.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum JsonLiteral {
+    Literal {
+        #[serde(rename = "_type")]
+        _literal_type: String,
+        text: String,
+    },
+    Text(String),
+    Value(Value),
+}
+
+impl JsonLiteral {
+    fn to_text(&self) -> String {
+        match self {
+            Self::Literal { text, .. } | Self::Text(text) => text.trim().to_string(),
+            Self::Value(v) => json_to_nix(v),
+        }
+    }
+
+    /// Plain strings are values when they appear as defaults or examples, and must be quoted to read as Nix.
+    fn to_value_text(&self) -> String {
+        match self {
+            Self::Text(text) => json_to_nix(&Value::String(text.clone())),
+            _ => self.to_text(),
+        }
+    }
+}
+
+/// Render a JSON value with Nix syntax, which is how the HTML manuals display defaults and examples.
+fn json_to_nix(v: &Value) -> String {
+    match v {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => format!("{s:?}"),
+        Value::Array(vs) if vs.is_empty() => "[ ]".to_string(),
+        Value::Array(vs) => format!(
+            "[ {} ]",
+            vs.iter().map(json_to_nix).collect::<Vec<_>>().join(" ")
+        ),
+        Value::Object(m) if m.is_empty() => "{ }".to_string(),
+        Value::Object(m) => format!(
+            "{{ {} }}",
+            m.iter()
+                .map(|(k, v)| format!("{k} = {};", json_to_nix(v)))
+                .collect::<Vec<_>>()
+                .join(" ")
+        ),
+    }
+}
+
+/// Parse the contents of an `options.json` file, which is an object mapping option names to option declarations.
+pub(crate) fn parse_options_json(json: &str) -> Result<Vec<OptText>> {
+    let opts: BTreeMap<String, JsonOpt> = serde_json::from_str(json)?;
+    Ok(opts.into_iter().map(OptText::from).collect())
+}
+
+impl From<(String, JsonOpt)> for OptText {
+    fn from((name, opt): (String, JsonOpt)) -> Self {
+        let type_model = FlatOptType::parse(&opt.var_type);
+        let var_type = if opt.read_only {
+            format!("{} (read only)", opt.var_type)
+        } else {
+            opt.var_type
+        };
+        let description = opt
+            .description
+            .as_ref()
+            .map(JsonLiteral::to_text)
+            .unwrap_or_default();
+        let example = opt
+            .example
+            .as_ref()
+            .map(JsonLiteral::to_value_text)
+            .unwrap_or_default();
+        Self {
+            // Matches the anchors used by the HTML manuals
+            id: format!("opt-{name}"),
+            loc: if opt.loc.is_empty() {
+                parse_location(&name)
+            } else {
+                opt.loc
+                    .iter()
+                    .map(|s| LocSegment::from(s.as_str()))
+                    .collect()
+            },
+            name,
+            rich_description: RichText::from_markdown(&description),
+            var_type,
+            type_model,
+            default: opt
+                .default
+                .as_ref()
+                .map(JsonLiteral::to_value_text)
+                .unwrap_or_default(),
+            rich_example: RichText::code(&example),
+            declared_by: opt
+                .declarations
+                .iter()
+                .map(JsonDeclaration::name)
+                .collect::<Vec<_>>()
+                .join("\n"),
+            declared_by_urls: opt
+                .declarations
+                .iter()
+                .filter_map(JsonDeclaration::url)
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsing::OptType;
+
+    const OPTIONS_JSON: &str = r#"{
+  "services.foo.enable": {
+    "declarations": ["nixos/modules/services/foo.nix"],
+    "default": false,
+    "description": "Whether to enable foo.",
+    "example": true,
+    "loc": ["services", "foo", "enable"],
+    "readOnly": false,
+    "type": "boolean"
+  },
+  "services.foo.settings": {
+    "declarations": [{"name": "<home-manager/modules/foo.nix>", "url": "https://github.com/nix-community/home-manager/blob/master/modules/foo.nix"}],
+    "default": {"_type": "literalExpression", "text": "{ }"},
+    "description": {"_type": "mdDoc", "text": "Settings for foo."},
+    "example": {"port": 80, "hosts": ["a", "b"]},
+    "loc": ["services", "foo", "settings"],
+    "readOnly": false,
+    "type": "attribute set of anything"
+  },
+  "system.build.foo": {
+    "declarations": ["/nix/store/abc-source/modules/build.nix"],
+    "description": "The foo build.",
+    "loc": ["system", "build", "foo"],
+    "readOnly": true,
+    "type": "package"
+  }
+}"#;
+
+    #[test]
+    fn parse_options_json_fields() -> Result<()> {
+        let opts = parse_options_json(OPTIONS_JSON)?;
+        assert_eq!(opts.len(), 3);
+
+        let enable = &opts[0];
+        assert_eq!(enable.name, "services.foo.enable");
+        assert_eq!(enable.id, "opt-services.foo.enable");
+        assert_eq!(enable.loc, parse_location(&enable.name));
+        assert_eq!(enable.var_type, "boolean");
+        assert_eq!(enable.opt_type(), Some(OptType::Bool));
+        assert_eq!(enable.default, "false");
+        assert_eq!(enable.rich_example.to_plain(), "true");
+        assert_eq!(enable.declared_by, "nixos/modules/services/foo.nix");
+        assert!(enable.declared_by_urls.is_empty());
+
+        let settings = &opts[1];
+        assert_eq!(settings.rich_description.to_plain(), "Settings for foo.");
+        assert_eq!(settings.default, "{ }");
+        assert_eq!(
+            settings.rich_example.to_plain(),
+            r#"{ hosts = [ "a" "b" ]; port = 80; }"#
+        );
+        assert_eq!(
+            settings.declared_by_urls,
+            vec!["https://github.com/nix-community/home-manager/blob/master/modules/foo.nix"]
+        );
+
+        let build = &opts[2];
+        assert_eq!(build.var_type, "package (read only)");
+        assert_eq!(build.default, "");
+        assert_eq!(
+            build.declared_by_urls,
+            vec!["file:///nix/store/abc-source/modules/build.nix"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_options_json_rejects_garbage() {
+        assert!(parse_options_json("<html></html>").is_err());
+    }
+}
@@ -0,0 +1,243 @@
+use bitcode::{Decode, Encode};
+use color_eyre::eyre::Result;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// A fully parsed package with fields formatted as raw text ready to print
+#[derive(Clone, Debug, Encode, Decode, PartialEq)]
+pub(crate) struct PkgText {
+    /// The attribute name in nixpkgs, e.g. `python3Packages.requests`
+    pub(crate) attr_name: String,
+    pub(crate) pname: String,
+    pub(crate) version: String,
+    pub(crate) description: String,
+    pub(crate) license: String,
+    pub(crate) homepage: String,
+    /// Where the package is defined, as `path:line`
+    pub(crate) position: String,
+    pub(crate) position_url: Option<String>,
+}
+
+/// `packages.json` as published in the nixpkgs channels is wrapped in a versioned object, while `nix-env -qa --json --meta` outputs the package set directly.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum JsonPackages {
+    Versioned { packages: BTreeMap<String, JsonPkg> },
+    Bare(BTreeMap<String, JsonPkg>),
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonPkg {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    pname: String,
+    #[serde(default)]
+    version: String,
+    #[serde(default)]
+    meta: JsonMeta,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct JsonMeta {
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    license: Option<OneOrMany<JsonLicense>>,
+    #[serde(default)]
+    homepage: Option<OneOrMany<String>>,
+    #[serde(default)]
+    position: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    fn iter(&self) -> std::slice::Iter<'_, T> {
+        match self {
+            Self::One(t) => std::slice::from_ref(t).iter(),
+            Self::Many(ts) => ts.iter(),
+        }
+    }
+}
+
+/// Licenses are attribute sets from `lib.licenses`, but old or out-of-tree packages may just use a string.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+#[allow(clippy::struct_field_names)]
+enum JsonLicense {
+    Named {
+        #[serde(rename = "spdxId")]
+        spdx_id: Option<String>,
+        #[serde(rename = "shortName")]
+        short_name: Option<String>,
+        #[serde(rename = "fullName")]
+        full_name: Option<String>,
+    },
+    Text(String),
+}
+
+impl JsonLicense {
+    fn to_text(&self) -> Option<&str> {
+        match self {
+            Self::Named {
+                spdx_id,
+                short_name,
+                full_name,
+            } => spdx_id
+                .as_deref()
+                .or(short_name.as_deref())
+                .or(full_name.as_deref()),
+            Self::Text(text) => Some(text),
+        }
+    }
+}
+
+/// Parse the contents of a nixpkgs `packages.json` file, linking the positions of packages in nixpkgs to `revision` of it.
+pub(crate) fn parse_packages_json(json: &str, revision: &str) -> Result<Vec<PkgText>> {
+    let packages = match serde_json::from_str(json)? {
+        JsonPackages::Versioned { packages } | JsonPackages::Bare(packages) => packages,
+    };
+    Ok(packages
+        .into_iter()
+        .map(|(attr_name, pkg)| PkgText::from_json(attr_name, pkg, revision))
+        .collect())
+}
+
+/// Positions are either relative to the nixpkgs root, or absolute paths which for channel builds point into a nixpkgs source in the store.
+/// Links to nixpkgs files point at `revision` on GitHub, everything else at the local file.
+fn position_url(position: &str, revision: &str) -> String {
+    let (path, line) = match position.rsplit_once(':') {
+        Some((path, line)) if line.chars().all(|c| c.is_ascii_digit()) => (path, Some(line)),
+        _ => (position, None),
+    };
+    let nixpkgs_path = match path.strip_prefix("/nix/store/") {
+        Some(store_path) => store_path
+            .split_once('/')
+            .filter(|(name, _)| name.ends_with("-source"))
+            .map(|(_, p)| p),
+        None if path.starts_with('/') => None,
+        None => Some(path),
+    };
+    match (nixpkgs_path, line) {
+        (Some(p), Some(l)) => format!("https://github.com/NixOS/nixpkgs/blob/{revision}/{p}#L{l}"),
+        (Some(p), None) => format!("https://github.com/NixOS/nixpkgs/blob/{revision}/{p}"),
+        (None, _) => format!("file://{path}"),
+    }
+}
+
+impl PkgText {
+    fn from_json(attr_name: String, pkg: JsonPkg, revision: &str) -> Self {
+        let position = pkg.meta.position.unwrap_or_default();
+        Self {
+            position_url: (!position.is_empty()).then(|| position_url(&position, revision)),
+            attr_name,
+            pname: if pkg.pname.is_empty() {
+                pkg.name
+            } else {
+                pkg.pname
+            },
+            version: pkg.version,
+            description: pkg.meta.description.unwrap_or_default().trim().to_string(),
+            license: pkg
+                .meta
+                .license
+                .iter()
+                .flat_map(OneOrMany::iter)
+                .filter_map(JsonLicense::to_text)
+                .collect::<Vec<_>>()
+                .join(", "),
+            homepage: pkg
+                .meta
+                .homepage
+                .iter()
+                .flat_map(OneOrMany::iter)
+                .next()
+                .cloned()
+                .unwrap_or_default(),
+            position,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACKAGES_JSON: &str = r#"{
+  "version": 2,
+  "packages": {
+    "hello": {
+      "meta": {
+        "description": "Program that produces a familiar, friendly greeting",
+        "homepage": "https://www.gnu.org/software/hello/manual/",
+        "license": {"fullName": "GNU General Public License v3.0 or later", "shortName": "gpl3Plus", "spdxId": "GPL-3.0-or-later"},
+        "position": "/nix/store/abc-source/pkgs/by-name/he/hello/package.nix:34"
+      },
+      "name": "hello-2.12.1",
+      "pname": "hello",
+      "version": "2.12.1"
+    },
+    "python3Packages.requests": {
+      "meta": {
+        "homepage": ["http://docs.python-requests.org/en/latest/", "https://github.com/psf/requests"],
+        "license": [{"spdxId": "Apache-2.0"}, "custom"],
+        "position": "pkgs/development/python-modules/requests/default.nix:20"
+      },
+      "name": "python3.12-requests-2.32.3",
+      "version": "2.32.3"
+    }
+  }
+}"#;
+
+    #[test]
+    fn parse_packages_json_fields() -> Result<()> {
+        let pkgs = parse_packages_json(PACKAGES_JSON, "nixos-24.11")?;
+        assert_eq!(pkgs.len(), 2);
+
+        let hello = &pkgs[0];
+        assert_eq!(hello.attr_name, "hello");
+        assert_eq!(hello.pname, "hello");
+        assert_eq!(hello.version, "2.12.1");
+        assert_eq!(hello.license, "GPL-3.0-or-later");
+        assert_eq!(hello.homepage, "https://www.gnu.org/software/hello/manual/");
+        assert_eq!(
+            hello.position_url.as_deref(),
+            Some("https://github.com/NixOS/nixpkgs/blob/nixos-24.11/pkgs/by-name/he/hello/package.nix#L34")
+        );
+
+        let requests = &pkgs[1];
+        assert_eq!(requests.pname, "python3.12-requests-2.32.3");
+        assert_eq!(requests.description, "");
+        assert_eq!(requests.license, "Apache-2.0, custom");
+        assert_eq!(
+            requests.homepage,
+            "http://docs.python-requests.org/en/latest/"
+        );
+        assert_eq!(
+            requests.position_url.as_deref(),
+            Some("https://github.com/NixOS/nixpkgs/blob/nixos-24.11/pkgs/development/python-modules/requests/default.nix#L20")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_bare_package_set() -> Result<()> {
+        let pkgs = parse_packages_json(
+            r#"{"mytool": {"name": "mytool-0.1", "meta": {"position": "/home/me/flake/pkgs/mytool.nix:3"}}}"#,
+            "master",
+        )?;
+        assert_eq!(pkgs.len(), 1);
+        assert_eq!(pkgs[0].pname, "mytool-0.1");
+        assert_eq!(
+            pkgs[0].position_url.as_deref(),
+            Some("file:///home/me/flake/pkgs/mytool.nix")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,548 @@
+use bitcode::{Decode, Encode};
+use tl::{HTMLTag, Node, Parser, ParserOptions};
+
+/// Documentation text with the formatting that matters for reading it: code, line breaks, list items and links
+#[derive(Clone, Debug, Default, Encode, Decode, PartialEq)]
+pub(crate) struct RichText(pub(crate) Vec<Block>);
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq)]
+pub(crate) enum Block {
+    Paragraph(Vec<Span>),
+    /// Preformatted text, whose line breaks and indentation are kept
+    Code(String),
+    /// An item of a (possibly nested) bulleted or numbered list
+    ListItem(Vec<Span>),
+}
+
+#[derive(Clone, Debug, Encode, Decode, PartialEq)]
+pub(crate) struct Span {
+    pub(crate) text: String,
+    pub(crate) kind: SpanKind,
+    /// The target of the link this span is part of
+    pub(crate) link: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Encode, Decode, PartialEq, Eq)]
+pub(crate) enum SpanKind {
+    #[default]
+    Plain,
+    Code,
+    Emphasis,
+    Strong,
+}
+
+impl Span {
+    pub(crate) fn plain(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            kind: SpanKind::Plain,
+            link: None,
+        }
+    }
+}
+
+impl RichText {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// A single block of preformatted text, or nothing if `code` is empty
+    pub(crate) fn code(code: &str) -> Self {
+        let code = code.trim_matches('\n');
+        if code.trim().is_empty() {
+            Self::default()
+        } else {
+            Self(vec![Block::Code(code.to_string())])
+        }
+    }
+
+    pub(crate) fn from_html(html: &str) -> Self {
+        let Ok(dom) = tl::parse(html, ParserOptions::default()) else {
+            return Self::code(html);
+        };
+        let mut builder = Builder::default();
+        HtmlWalker {
+            p: dom.parser(),
+            builder: &mut builder,
+            kind: SpanKind::Plain,
+            link: None,
+        }
+        .children(dom.children().iter().copied());
+        builder.finish()
+    }
+
+    /// Markdown as used in the descriptions of the module system, including the roles of nixpkgs like ``{option}`foo` ``.
+    /// Only the constructs that matter for reading are recognized.
+    pub(crate) fn from_markdown(markdown: &str) -> Self {
+        let mut builder = Builder::default();
+        let mut lines = markdown.lines();
+        while let Some(line) = lines.next() {
+            let trimmed = line.trim_start();
+            if let Some(fence) = ["```", "~~~"].into_iter().find(|f| trimmed.starts_with(f)) {
+                builder.flush();
+                let indent = line.len() - trimmed.len();
+                let code = lines
+                    .by_ref()
+                    .take_while(|l| !l.trim_start().starts_with(fence))
+                    .map(|l| l.get(indent..).unwrap_or(l.trim_start()))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                builder.blocks.extend(Self::code(&code).0);
+            } else if trimmed.is_empty() || trimmed.starts_with(":::") {
+                // Admonitions like `::: {.note}` are shown as their contents
+                builder.flush();
+            } else if let Some(item) = list_item(trimmed) {
+                builder.flush();
+                builder.in_list_item = true;
+                builder.inline_markdown(item);
+            } else {
+                builder.inline_markdown(trimmed);
+            }
+        }
+        builder.finish()
+    }
+
+    /// Remove a label like `Example:` that the HTML manuals put before a field
+    pub(crate) fn strip_prefix(mut self, prefix: &str) -> Self {
+        if let Some(Block::Paragraph(spans)) = self.0.first_mut() {
+            let text = spans.iter().map(|s| s.text.as_str()).collect::<String>();
+            if text.trim_start().starts_with(prefix) {
+                let mut remaining = prefix.len() + text.len() - text.trim_start().len();
+                let mut trimming = true;
+                spans.retain_mut(|span| {
+                    if !trimming {
+                        return true;
+                    }
+                    let cut = remaining.min(span.text.len());
+                    remaining -= cut;
+                    span.text = span.text[cut..].to_string();
+                    if remaining == 0 {
+                        // The whitespace after the prefix may continue into the next span
+                        span.text = span.text.trim_start().to_string();
+                        trimming = span.text.is_empty();
+                    }
+                    !span.text.is_empty()
+                });
+                if spans.is_empty() {
+                    self.0.remove(0);
+                }
+            }
+        }
+        self
+    }
+
+    /// The text without formatting, with each block on its own line and a bullet before list items
+    pub(crate) fn to_plain(&self) -> String {
+        let spans_text = |spans: &[Span]| spans.iter().map(|s| s.text.as_str()).collect::<String>();
+        self.0
+            .iter()
+            .map(|block| match block {
+                Block::Paragraph(spans) => spans_text(spans),
+                Block::ListItem(spans) => format!("• {}", spans_text(spans)),
+                Block::Code(code) => code.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The targets of all links, which may be relative to the page the text is from
+    pub(crate) fn links_mut(&mut self) -> impl Iterator<Item = &mut String> {
+        self.0
+            .iter_mut()
+            .flat_map(|block| match block {
+                Block::Paragraph(spans) | Block::ListItem(spans) => spans.as_mut_slice(),
+                Block::Code(_) => &mut [],
+            })
+            .filter_map(|span| span.link.as_mut())
+    }
+}
+
+/// The text of a Markdown list item, without its marker
+fn list_item(line: &str) -> Option<&str> {
+    if let Some(item) = ["- ", "* ", "+ "].iter().find_map(|m| line.strip_prefix(m)) {
+        return Some(item);
+    }
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    line[digits..].strip_prefix(". ").filter(|_| digits > 0)
+}
+
+/// Collects spans into blocks
+#[derive(Default)]
+struct Builder {
+    blocks: Vec<Block>,
+    spans: Vec<Span>,
+    in_list_item: bool,
+}
+
+impl Builder {
+    fn push(&mut self, text: &str, kind: SpanKind, link: Option<&String>) {
+        if text.is_empty() {
+            return;
+        }
+        match self.spans.last_mut() {
+            Some(last) if last.kind == kind && last.link.as_ref() == link => {
+                last.text.push_str(text);
+            }
+            _ => self.spans.push(Span {
+                text: text.to_string(),
+                kind,
+                link: link.cloned(),
+            }),
+        }
+    }
+
+    /// Add text from a source in which line breaks and indentation don't matter
+    fn push_collapsed(&mut self, text: &str, kind: SpanKind, link: Option<&String>) {
+        let mut collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if text.starts_with(char::is_whitespace) && !collapsed.is_empty() {
+            collapsed.insert(0, ' ');
+        }
+        if text.ends_with(char::is_whitespace) && !collapsed.is_empty() {
+            collapsed.push(' ');
+        }
+        if collapsed.trim().is_empty() {
+            collapsed = if text.is_empty() {
+                String::new()
+            } else {
+                " ".to_string()
+            };
+        }
+        // No whitespace at the start of a paragraph or after a line break
+        let at_line_start = self
+            .spans
+            .last()
+            .is_none_or(|s| s.text.ends_with([' ', '\n']));
+        if at_line_start {
+            collapsed = collapsed.trim_start().to_string();
+        }
+        self.push(&collapsed, kind, link);
+    }
+
+    fn flush(&mut self) {
+        if let Some(last) = self.spans.last_mut() {
+            last.text.truncate(last.text.trim_end().len());
+        }
+        self.spans.retain(|s| !s.text.is_empty());
+        if !self.spans.is_empty() {
+            let spans = std::mem::take(&mut self.spans);
+            self.blocks.push(if self.in_list_item {
+                Block::ListItem(spans)
+            } else {
+                Block::Paragraph(spans)
+            });
+        }
+        self.in_list_item = false;
+    }
+
+    fn finish(mut self) -> RichText {
+        self.flush();
+        RichText(self.blocks)
+    }
+
+    fn inline_markdown(&mut self, text: &str) {
+        if !self.spans.is_empty() {
+            self.push(" ", SpanKind::Plain, None);
+        }
+        self.inline_markdown_with(text, SpanKind::Plain, None);
+    }
+
+    fn inline_markdown_with(&mut self, text: &str, kind: SpanKind, link: Option<&String>) {
+        let mut rest = text;
+        let mut plain = String::new();
+        while let Some(c) = rest.chars().next() {
+            let special = match c {
+                '\\' if rest.len() > 1 => {
+                    let escaped = rest[1..].chars().next().unwrap_or('\\');
+                    plain.push(escaped);
+                    rest = &rest[1 + escaped.len_utf8()..];
+                    continue;
+                }
+                // Roles like {option}`foo` are rendered as their code
+                '{' => role_end(rest).map(|end| {
+                    rest = &rest[end..];
+                    None
+                }),
+                '`' => {
+                    code_span(rest).map(|(code, after)| Some((code, SpanKind::Code, None, after)))
+                }
+                '[' => markdown_link(rest)
+                    .map(|(label, target, after)| Some((label, kind, Some(target), after))),
+                '<' if rest.starts_with("<http") => rest.find('>').map(|end| {
+                    let target = &rest[1..end];
+                    Some((target, kind, Some(target), &rest[end + 1..]))
+                }),
+                '*' => {
+                    let delimiter = if rest.starts_with("**") { "**" } else { "*" };
+                    delimited(rest, delimiter).map(|(inner, after)| {
+                        let kind = if delimiter == "**" {
+                            SpanKind::Strong
+                        } else {
+                            SpanKind::Emphasis
+                        };
+                        Some((inner, kind, None, after))
+                    })
+                }
+                _ => None,
+            };
+            let (text, span_kind, target, after) = match special {
+                Some(Some(span)) => span,
+                // A role was skipped
+                Some(None) => continue,
+                None => {
+                    plain.push(c);
+                    rest = &rest[c.len_utf8()..];
+                    continue;
+                }
+            };
+            self.push(&plain, kind, link);
+            plain.clear();
+            let target = target.map(str::to_string);
+            match span_kind {
+                SpanKind::Code => self.push(text, span_kind, target.as_ref().or(link)),
+                _ => self.inline_markdown_with(text, span_kind, target.as_ref().or(link)),
+            }
+            rest = after;
+        }
+        self.push(&plain, kind, link);
+    }
+}
+
+/// The length of a role like `{option}` that is directly followed by a code span
+fn role_end(s: &str) -> Option<usize> {
+    let end = s.find('}')?;
+    let role = &s[1..end];
+    (!role.is_empty()
+        && role.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && s[end + 1..].starts_with('`'))
+    .then_some(end + 1)
+}
+
+/// A code span at the start of `s`, delimited by any number of backticks, and the rest after it
+fn code_span(s: &str) -> Option<(&str, &str)> {
+    let ticks = s.len() - s.trim_start_matches('`').len();
+    let delimiter = &s[..ticks];
+    let end = s[ticks..].find(delimiter)? + ticks;
+    Some((s[ticks..end].trim(), &s[end + ticks..]))
+}
+
+/// A link `[label](target)` at the start of `s`, and the rest after it
+fn markdown_link(s: &str) -> Option<(&str, &str, &str)> {
+    let label_end = s.find("](")?;
+    let target_end = label_end + 2 + s[label_end + 2..].find(')')?;
+    let label = &s[1..label_end];
+    let target = &s[label_end + 2..target_end];
+    // Links to options with an empty label (`[](#opt-foo)`) are labelled by their target
+    let label = if label.is_empty() { target } else { label };
+    Some((label, target, &s[target_end + 1..]))
+}
+
+/// Text between a pair of `delimiter`s at the start of `s`, and the rest after it
+fn delimited<'a>(s: &'a str, delimiter: &str) -> Option<(&'a str, &'a str)> {
+    let inner = s.strip_prefix(delimiter)?;
+    if inner.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let end = inner.find(delimiter)?;
+    (end > 0).then(|| (&inner[..end], &inner[end + delimiter.len()..]))
+}
+
+struct HtmlWalker<'a, 'b> {
+    p: &'a Parser<'a>,
+    builder: &'b mut Builder,
+    kind: SpanKind,
+    link: Option<String>,
+}
+
+impl HtmlWalker<'_, '_> {
+    fn children(&mut self, handles: impl Iterator<Item = tl::NodeHandle>) {
+        for handle in handles {
+            match handle.get(self.p) {
+                Some(Node::Raw(text)) => {
+                    let text = decode_entities(&text.as_utf8_str());
+                    self.builder
+                        .push_collapsed(&text, self.kind, self.link.as_ref());
+                }
+                Some(Node::Tag(tag)) => self.tag(tag),
+                _ => (),
+            }
+        }
+    }
+
+    fn tag(&mut self, tag: &HTMLTag) {
+        let name = tag.name().as_utf8_str().to_ascii_lowercase();
+        let children = tag.children().top().to_vec();
+        match name.as_str() {
+            "pre" => {
+                self.builder.flush();
+                let code = decode_entities(&tag.inner_text(self.p));
+                self.builder.blocks.extend(RichText::code(&code).0);
+            }
+            "br" => self.builder.push("\n", self.kind, self.link.as_ref()),
+            "li" => {
+                self.builder.flush();
+                self.builder.in_list_item = true;
+                self.children(children.into_iter());
+                self.builder.flush();
+            }
+            "p" | "div" | "ul" | "ol" | "dl" | "dt" | "dd" | "table" | "tr" | "blockquote"
+            | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let in_list_item = self.builder.in_list_item && self.builder.spans.is_empty();
+                self.builder.flush();
+                self.builder.in_list_item = in_list_item;
+                self.children(children.into_iter());
+                self.builder.flush();
+            }
+            _ => {
+                let kind = match name.as_str() {
+                    "code" | "tt" | "kbd" | "samp" | "literal" => SpanKind::Code,
+                    "em" | "i" => SpanKind::Emphasis,
+                    "strong" | "b" => SpanKind::Strong,
+                    _ => self.kind,
+                };
+                let link = match tag.attributes().get("href").flatten() {
+                    Some(href) if name == "a" => Some(decode_entities(&href.as_utf8_str())),
+                    _ => self.link.clone(),
+                };
+                let kind = std::mem::replace(&mut self.kind, kind);
+                let link = std::mem::replace(&mut self.link, link);
+                self.children(children.into_iter());
+                self.kind = kind;
+                self.link = link;
+            }
+        }
+    }
+}
+
+/// Decode the character references that occur in the manuals
+fn decode_entities(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => '\u{a0}',
+                entity => {
+                    let number = entity.strip_prefix('#')?;
+                    let code = match number.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => number.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((c, end))
+        });
+        let (c, len) = decoded.map_or(('&', 1), |(c, end)| (c, end + 1));
+        out.push(c);
+        rest = &rest[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(text: &str, kind: SpanKind, link: Option<&str>) -> Span {
+        Span {
+            text: text.to_string(),
+            kind,
+            link: link.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn rich_text_from_html() {
+        let html = r#"<p>Whether to enable the
+<code class="literal">foo</code> service, see <a href="https://foo.example/docs">the &lt;docs&gt;</a>.</p>
+<div class="itemizedlist"><ul><li><p>First</p></li><li><p>Second</p></li></ul></div>
+<pre><code class="programlisting">{
+  foo = 1;
+}</code></pre>"#;
+        assert_eq!(
+            RichText::from_html(html).0,
+            vec![
+                Block::Paragraph(vec![
+                    span("Whether to enable the ", SpanKind::Plain, None),
+                    span("foo", SpanKind::Code, None),
+                    span(" service, see ", SpanKind::Plain, None),
+                    span(
+                        "the <docs>",
+                        SpanKind::Plain,
+                        Some("https://foo.example/docs")
+                    ),
+                    span(".", SpanKind::Plain, None),
+                ]),
+                Block::ListItem(vec![span("First", SpanKind::Plain, None)]),
+                Block::ListItem(vec![span("Second", SpanKind::Plain, None)]),
+                Block::Code("{\n  foo = 1;\n}".to_string()),
+            ]
+        );
+
+        let example = RichText::from_html(
+            r#"<p><span class="emphasis"><em>Example:</em></span></p><pre><code>"bar"</code></pre>"#,
+        );
+        assert_eq!(
+            example.strip_prefix("Example:").0,
+            vec![Block::Code(r#""bar""#.to_string())]
+        );
+
+        let inline = RichText::from_html("<p><em>Example:</em> use <code>foo</code> here</p>");
+        assert_eq!(
+            inline.strip_prefix("Example:").0,
+            vec![Block::Paragraph(vec![
+                span("use ", SpanKind::Plain, None),
+                span("foo", SpanKind::Code, None),
+                span(" here", SpanKind::Plain, None),
+            ])]
+        );
+    }
+
+    #[test]
+    fn rich_text_from_markdown() {
+        let markdown = r"Set {option}`services.foo.enable` to **true**, see
+[the manual](https://foo.example) or <https://bar.example>.
+
+- One \* item
+- Another
+
+```nix
+{ foo = 1; }
+```";
+        assert_eq!(
+            RichText::from_markdown(markdown).0,
+            vec![
+                Block::Paragraph(vec![
+                    span("Set ", SpanKind::Plain, None),
+                    span("services.foo.enable", SpanKind::Code, None),
+                    span(" to ", SpanKind::Plain, None),
+                    span("true", SpanKind::Strong, None),
+                    span(", see ", SpanKind::Plain, None),
+                    span("the manual", SpanKind::Plain, Some("https://foo.example")),
+                    span(" or ", SpanKind::Plain, None),
+                    span(
+                        "https://bar.example",
+                        SpanKind::Plain,
+                        Some("https://bar.example")
+                    ),
+                    span(".", SpanKind::Plain, None),
+                ]),
+                Block::ListItem(vec![span("One * item", SpanKind::Plain, None)]),
+                Block::ListItem(vec![span("Another", SpanKind::Plain, None)]),
+                Block::Code("{ foo = 1; }".to_string()),
+            ]
+        );
+        assert_eq!(
+            RichText::from_markdown(markdown).to_plain(),
+            "Set services.foo.enable to true, see the manual or https://bar.example.\n• One * item\n• Another\n{ foo = 1; }"
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use crate::parsing::PkgText;
+use crate::rich_text_display::wrapped_height;
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Padding, Paragraph, Wrap},
+};
+
+/// A widget to display a single package parsed from a nixpkgs `packages.json`.
+/// Layout:
+/// ######################################################
+/// # Name: ...          Version: ...       License: ... #
+/// # Description: ...............          Homepage: .. #
+/// #     ........................          Position: .. #
+/// ######################################################
+
+#[derive(Clone)]
+pub struct PkgListItem {
+    pub content: PkgText,
+    style: Style,
+}
+
+impl PkgListItem {
+    const DEFAULT_HEIGHT: u16 = 4;
+
+    pub fn new(value: PkgText) -> Self {
+        PkgListItem {
+            content: value,
+            style: Style::default(),
+        }
+    }
+}
+
+impl Widget for PkgListItem {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let title_style = Style::new().blue();
+
+        let name = Paragraph::new(Line::from(vec![
+            Span::styled("Name: ", title_style),
+            self.content.attr_name.clone().into(),
+        ]));
+        let version = Paragraph::new(Line::from(vec![
+            Span::styled("Version: ", title_style),
+            self.content.version.clone().into(),
+        ]));
+        let license = Paragraph::new(Line::from(vec![
+            Span::styled("License: ", title_style),
+            self.content.license.clone().into(),
+        ]));
+        let description = Paragraph::new(self.description_lines()).wrap(Wrap { trim: true });
+        let links = Paragraph::new(self.link_lines()).wrap(Wrap { trim: true });
+
+        let block = Block::default()
+            .style(self.style)
+            .padding(Padding::bottom(1));
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let outer = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Percentage(100)])
+            .split(inner);
+
+        let inner_top = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Ratio(1, 3); 3])
+            .split(outer[0]);
+        let inner_bottom = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Ratio(2, 3), Constraint::Ratio(1, 3)])
+            .split(outer[1]);
+
+        name.render(inner_top[0], buf);
+        version.render(inner_top[1], buf);
+        license.render(inner_top[2], buf);
+
+        description.render(inner_bottom[0], buf);
+        links.render(inner_bottom[1], buf);
+    }
+}
+
+impl PkgListItem {
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn pre_render(&mut self, context: &tui_widget_list::ListBuildContext) -> u16 {
+        self.style = if context.is_selected {
+            Style::default().bg(Color::DarkGray)
+        } else if context.index % 2 == 0 {
+            Style::default()
+        } else {
+            Style::default().bg(Color::Indexed(236))
+        };
+        self.full_height(context.cross_axis_size)
+    }
+}
+
+impl PkgListItem {
+    fn full_height(&self, width: u16) -> u16 {
+        // Description and links are laid out next to each other at a 2:1 ratio.
+        let description_height = wrapped_height(&self.description_lines(), width * 2 / 3);
+        let links_height = wrapped_height(&self.link_lines(), width / 3);
+
+        (description_height.max(links_height) + 2).max(PkgListItem::DEFAULT_HEIGHT)
+    }
+
+    fn description_lines(&self) -> Vec<Line<'static>> {
+        vec![Line::from(vec![
+            Span::styled("Description: ", Style::new().blue()),
+            self.content.description.clone().into(),
+        ])]
+    }
+
+    fn link_lines(&self) -> Vec<Line<'static>> {
+        let title_style = Style::new().blue();
+        vec![
+            Line::from(vec![
+                Span::styled("Homepage: ", title_style),
+                self.content.homepage.clone().into(),
+            ]),
+            Line::from(vec![
+                Span::styled("Position: ", title_style),
+                self.content.position.clone().into(),
+            ]),
+        ]
+    }
+}
@@ -0,0 +1,72 @@
+use crate::parsing::{Block, RichText, SpanKind};
+use ratatui::prelude::*;
+
+/// The lines of `text` in the styles of the list items: code is cyan, code blocks are indented, list items get a bullet, and links are underlined.
+/// `title` starts the first line, and is the only content if `text` is empty.
+pub fn rich_text_lines(title: Span<'static>, text: &RichText) -> Vec<Line<'static>> {
+    let mut lines = vec![];
+    for block in &text.0 {
+        match block {
+            Block::Paragraph(spans) => lines.extend(span_lines(spans, "")),
+            Block::ListItem(spans) => lines.extend(span_lines(spans, "  • ")),
+            Block::Code(code) => lines.extend(
+                code.lines()
+                    .map(|l| Line::styled(format!("  {l}"), code_style())),
+            ),
+        }
+    }
+    match lines.first_mut() {
+        Some(first) => first.spans.insert(0, title),
+        None => lines.push(Line::from(title)),
+    }
+    lines
+}
+
+/// The number of rows `lines` take up when wrapped at `width`
+pub fn wrapped_height(lines: &[Line], width: u16) -> u16 {
+    let width = usize::from(width.max(1));
+    let rows: usize = lines.iter().map(|l| l.width().div_ceil(width).max(1)).sum();
+    u16::try_from(rows).unwrap_or(u16::MAX)
+}
+
+fn code_style() -> Style {
+    Style::new().cyan()
+}
+
+/// Paragraph spans split into lines at their line breaks, with `prefix` before the first line
+fn span_lines(spans: &[crate::parsing::Span], prefix: &'static str) -> Vec<Line<'static>> {
+    let mut lines = vec![Line::from(prefix)];
+    for (i, span) in spans.iter().enumerate() {
+        let mut style = match span.kind {
+            SpanKind::Plain => Style::new(),
+            SpanKind::Code => code_style(),
+            SpanKind::Emphasis => Style::new().italic(),
+            SpanKind::Strong => Style::new().bold(),
+        };
+        if span.link.is_some() {
+            style = style.underlined();
+        }
+        for (j, part) in span.text.split('\n').enumerate() {
+            if j > 0 {
+                lines.push(Line::default());
+            }
+            if !part.is_empty() {
+                lines
+                    .last_mut()
+                    .expect("there is at least one line")
+                    .push_span(Span::styled(part.to_string(), style));
+            }
+        }
+        // External links show where they point after their last span, unless the text already does
+        let link_ends = spans.get(i + 1).is_none_or(|next| next.link != span.link);
+        if let Some(link) = span.link.as_ref().filter(|_| link_ends) {
+            if link.starts_with("http") && *link != span.text {
+                lines
+                    .last_mut()
+                    .expect("there is at least one line")
+                    .push_span(Span::styled(format!(" <{link}>"), Style::new().dark_gray()));
+            }
+        }
+    }
+    lines
+}
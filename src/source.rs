@@ -1,27 +1,74 @@
-use crate::cache::Cacheable;
-use crate::config::SourceConfig;
-use crate::parsing::{parse_options, parse_version, OptText};
+use crate::cache::{CacheConfig, Cacheable, Revalidation};
+use crate::config::{AppConfig, HttpHeader, SourceConfig, SourceFormat, UrlRewrite};
+use crate::parsing::{
+    nix_module_files, parse_builtins, parse_lib_functions, parse_nix_module, parse_nix_modules,
+    parse_options, parse_options_json, parse_packages_json, parse_version, Entry, FunText, OptText,
+    ParseReport, PkgText,
+};
 use bitcode::{Decode, Encode};
-use color_eyre::eyre::Result;
-use lazy_regex::regex_replace_all;
+use color_eyre::eyre::{ensure, eyre, Result};
+use lazy_regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::fmt;
-use tracing::{error, instrument};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, error, instrument, warn};
+use ureq::http::header::{ETAG, LAST_MODIFIED};
+use ureq::http::StatusCode;
 
 #[derive(Debug, Clone, Encode, Decode, PartialEq, Deserialize, Serialize)]
 pub(crate) struct Source {
+    /// The configuration of the source, with the `{version}` placeholder replaced by `release`
     pub(crate) inner: SourceConfig,
+    /// One of the `releases` of the configuration, if it has any
+    #[serde(default)]
+    pub(crate) release: Option<String>,
 }
 
 impl Cacheable for Source {
     type WithData = SourceData;
+    // Increase when `SourceData` or any of the types in it change
+    const CACHE_SCHEMA: u32 = 1;
 
     fn get_expensive(&self) -> Result<Self::WithData> {
         self.get_online_data()
     }
 
+    fn get_expensive_since(&self, cached: &Self::WithData) -> Result<Revalidation<Self::WithData>> {
+        self.get_online_data_since(cached)
+    }
+
+    /// Local sources are refreshed whenever their files change, instead of after a fixed duration.
+    fn last_modified(&self) -> Option<SystemTime> {
+        if self.inner.command.is_some() {
+            return None;
+        }
+        let modified = |p: Result<PathBuf>| p.ok()?.metadata().ok()?.modified().ok();
+        if self.inner.format == SourceFormat::NixModules {
+            let files = nix_module_files(&self.local_data_path()?.ok()?).ok()?;
+            return files.into_iter().filter_map(|f| modified(Ok(f))).max();
+        }
+        let data_modified = modified(self.local_data_path()?)?;
+        if self.inner.version_url.is_none() {
+            return Some(data_modified);
+        }
+        match self.local_version_path() {
+            Some(p) => Some(data_modified.max(modified(p)?)),
+            None => Some(data_modified),
+        }
+    }
+
+    /// Local files are read in offline mode too, but commands might use the network
+    fn needs_network(&self) -> bool {
+        self.inner.command.is_some()
+            || local_path(self.url()).is_none()
+            || (self.inner.version_url.is_some() && local_path(self.version_url()).is_none())
+    }
+
     fn cache_valid(&self, data: &Self::WithData) -> crate::cache::CacheValidity {
-        if data.source == *self {
+        if data.source == self.redacted() {
             crate::cache::CacheValidity::Good
         } else if data.source.url() == self.url() {
             crate::cache::CacheValidity::Fallback
@@ -32,13 +79,67 @@ impl Cacheable for Source {
 }
 
 impl Source {
+    const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
     // From docs: Compression level 0 means "use zstd default compression level", currently 3
     pub(crate) fn from(source: &SourceConfig) -> Self {
+        Self::with_release(source, source.releases.first().map(String::as_str))
+    }
+
+    /// The source for `release`, which should be one of the `releases` of `source`
+    pub(crate) fn with_release(source: &SourceConfig, release: Option<&str>) -> Self {
         Self {
-            inner: source.clone(),
+            inner: release.map_or_else(|| source.clone(), |r| source.for_release(r)),
+            release: release.map(str::to_string),
         }
     }
 
+    /// The source for each release of `source`, or just the source if it has no releases
+    pub(crate) fn each_release(source: &SourceConfig) -> Vec<Self> {
+        if source.releases.is_empty() {
+            vec![Self::from(source)]
+        } else {
+            source
+                .releases
+                .iter()
+                .map(|r| Self::with_release(source, Some(r)))
+                .collect()
+        }
+    }
+
+    /// This source as it is stored in caches, without the literal values of its headers
+    pub(crate) fn redacted(&self) -> Self {
+        let mut source = self.clone();
+        for header in &mut source.inner.headers {
+            header.value = None;
+        }
+        source
+    }
+
+    /// The file in `dir` that the data of this source is cached in
+    pub(crate) fn cache_file(&self, dir: &Path) -> PathBuf {
+        dir.join(format!("{self}.zst"))
+    }
+
+    /// Where the data of this source is cached, if caching is enabled, and how
+    pub(crate) fn cache_config(&self, config: &AppConfig) -> CacheConfig {
+        CacheConfig {
+            file: config.cache_dir.as_deref().map(|d| self.cache_file(d)),
+            system_files: config
+                .system_cache_dirs
+                .iter()
+                .map(|d| self.cache_file(d))
+                .collect(),
+            duration: config.cache_duration,
+            offline: config.offline,
+        }
+    }
+
+    /// The releases this source can be switched between
+    pub(crate) fn releases(&self) -> &[String] {
+        &self.inner.releases
+    }
+
     pub(crate) fn url(&self) -> &str {
         &self.inner.url
     }
@@ -47,91 +148,569 @@ impl Source {
         self.inner.version_url.as_ref().unwrap_or(&self.inner.url)
     }
 
-    pub(crate) fn doc_url_to(&self, opt: &OptText) -> String {
-        format!("{}#{}", self.url(), opt.id)
+    pub(crate) fn doc_url_to(&self, entry: &Entry) -> String {
+        let url = match entry {
+            // Modules have no rendered documentation to link to, and their declarations are already rewritten
+            Entry::Opt(OptText {
+                declared_by_urls, ..
+            }) if self.inner.format == SourceFormat::NixModules => {
+                return declared_by_urls.first().cloned().unwrap_or_default();
+            }
+            Entry::Opt(OptText { id, .. }) | Entry::Fun(FunText { id, .. }) => {
+                self.doc_url_to_anchor(id)
+            }
+            Entry::Pkg(pkg) if !pkg.homepage.is_empty() => pkg.homepage.clone(),
+            Entry::Pkg(_) => self.url().to_string(),
+        };
+        UrlRewriter::new(&self.inner.url_rewrites).rewrite(&url)
+    }
+
+    fn doc_url_to_anchor(&self, id: &str) -> String {
+        match self.local_data_path() {
+            Some(Ok(path)) => {
+                let path = std::fs::canonicalize(&path).unwrap_or(path);
+                format!("file://{}#{id}", path.display())
+            }
+            _ => format!("{}#{id}", self.url()),
+        }
+    }
+
+    /// The absolute URL of a link relative to the documentation page, or `None` if it already is absolute.
+    /// Relative paths are only resolved for remote pages, since local copies usually lack the pages they point to.
+    fn resolve_link(&self, link: &str) -> Option<String> {
+        if let Some(anchor) = link.strip_prefix('#') {
+            return Some(self.doc_url_to_anchor(anchor));
+        }
+        if link.contains(':') || local_path(self.url()).is_some() {
+            return None;
+        }
+        let url = self.url();
+        let base = url.rfind('/').map_or(url, |i| &url[..=i]);
+        Some(format!("{base}{}", link.trim_start_matches("./")))
+    }
+
+    /// The branch of nixpkgs that package positions link to: The channel of a `url` on channels.nixos.org, e.g. "nixos-24.11", or else master.
+    fn nixpkgs_revision(&self) -> &str {
+        self.url()
+            .strip_prefix("https://channels.nixos.org/")
+            .and_then(|path| path.split('/').next())
+            .filter(|channel| !channel.is_empty())
+            .unwrap_or("master")
+    }
+
+    /// The local file containing the data of this source, if `url` is not a remote URL.
+    fn local_data_path(&self) -> Option<Result<PathBuf>> {
+        let candidates: &[&str] = match self.inner.format {
+            SourceFormat::Html => &["options.html", "index.html"],
+            SourceFormat::OptionsJson => &["options.json"],
+            SourceFormat::PackagesJson => &["packages.json"],
+            SourceFormat::NixpkgsLib => &["index.html"],
+            SourceFormat::NixBuiltins => &["builtins.html"],
+            // Directories of modules are read as a whole
+            SourceFormat::NixModules => return local_path(self.url()).map(Ok),
+        };
+        local_path(self.url()).map(|p| resolve_local_path(p, candidates))
     }
 
-    pub(crate) fn get_data_html(&self) -> Result<String> {
-        Ok(ureq::get(self.url())
-            .call()?
-            .body_mut()
-            .with_config()
-            // 30 MB reading limit.
-            // The default is 10MB, but the nixos docs are 20-21MB, at least uncompressed.
-            .limit(30 * 1024 * 1024)
-            .read_to_string()?)
+    /// The local file containing the version of this source, if `version_url` is not a remote URL.
+    fn local_version_path(&self) -> Option<Result<PathBuf>> {
+        local_path(self.version_url()).map(|p| resolve_local_path(p, &["index.html"]))
     }
 
-    pub(crate) fn get_version_html(&self) -> Result<String> {
-        Ok(ureq::get(self.version_url())
-            .call()?
-            .body_mut()
-            .read_to_string()?)
+    /// The data of this source
+    pub(crate) fn get_data_html(&self) -> Result<Page> {
+        if let Some(command) = &self.inner.command {
+            let output = run_command(
+                command,
+                self.inner
+                    .command_timeout
+                    .unwrap_or(Self::DEFAULT_COMMAND_TIMEOUT),
+            )?;
+            return Ok(Page::local(output));
+        }
+        if let Some(path) = self.local_data_path() {
+            return Ok(Page::local(std::fs::read_to_string(path?)?));
+        }
+        http_get_unconditional(self.url(), &self.request_headers()?)
+    }
+
+    /// The page containing the version of this source
+    pub(crate) fn get_version_html(&self) -> Result<Page> {
+        if let Some(path) = self.local_version_path() {
+            return Ok(Page::local(std::fs::read_to_string(path?)?));
+        }
+        http_get_unconditional(self.version_url(), &self.request_headers()?)
+    }
+
+    /// The configured headers, with their values read from the environment or files
+    fn request_headers(&self) -> Result<Vec<(String, String)>> {
+        self.inner.headers.iter().map(HttpHeader::resolve).collect()
     }
 
     pub(crate) fn parse_data(&self, data_html: &str, version_html: &str) -> Result<SourceData> {
-        let opts = parse_options(data_html)?;
+        let (mut opts, mut packages, mut functions) = (vec![], vec![], vec![]);
+        let mut parse_report = None;
+        match self.inner.format {
+            SourceFormat::Html => {
+                let (parsed, report) = parse_options(data_html)?;
+                opts = parsed;
+                parse_report = Some(report);
+            }
+            SourceFormat::OptionsJson => opts = parse_options_json(data_html)?,
+            SourceFormat::PackagesJson => {
+                packages = parse_packages_json(data_html, self.nixpkgs_revision())?;
+            }
+            SourceFormat::NixpkgsLib => functions = parse_lib_functions(data_html)?,
+            SourceFormat::NixBuiltins => {
+                let (parsed, report) = parse_builtins(data_html)?;
+                functions = parsed;
+                parse_report = Some(report);
+            }
+            SourceFormat::NixModules => opts = parse_nix_module(data_html, Path::new(self.url())),
+        }
 
-        let version = match parse_version(version_html) {
-            Ok(Some(version)) => version,
-            Ok(None) => "No version number found".to_string(),
+        let parse_report = parse_report.unwrap_or_else(|| {
+            ParseReport::complete(opts.len() + packages.len() + functions.len())
+        });
+        if !parse_report.skipped.is_empty() {
+            warn!(
+                "Skipped {} malformed entries of {}",
+                parse_report.skipped.len(),
+                self.inner.name
+            );
+        }
+        let mut data = SourceData {
+            source: self.redacted(),
+            opts,
+            packages,
+            functions,
+            version: self.parse_version(version_html).to_string(),
+            parse_report,
+            data_validators: HttpValidators::default(),
+            version_validators: HttpValidators::default(),
+        };
+        data.rewrite_urls();
+        Ok(data)
+    }
+
+    pub(crate) fn parse_version(&self, version_html: &str) -> SourceVersion {
+        match parse_version(version_html) {
+            // Make it visible when a release template leads to the documentation of a different release
+            Ok(Some(version)) => match &self.release {
+                Some(release) if !version.contains(release.as_str()) => {
+                    warn!(
+                        "{} has version {version} instead of release {release}",
+                        self.inner.name
+                    );
+                    SourceVersion::Mismatch {
+                        found: version,
+                        expected: release.clone(),
+                    }
+                }
+                _ => SourceVersion::Found(version),
+            },
+            Ok(None) => SourceVersion::NotFound,
             Err(err) => {
                 // Log error on failed version parsing, but keep running
                 error!(
                     "Parsing version number failed for {}: {err}",
                     self.inner.name
                 );
-                "Error parsing version".to_string()
+                SourceVersion::Error(err.to_string())
             }
-        };
-
-        let mut data = SourceData {
-            source: self.clone(),
-            opts,
-            version,
-        };
-        data.nixos_unstable_declared_by_hack();
-        Ok(data)
+        }
     }
 
     #[instrument(err, level = "debug")]
     pub(crate) fn get_online_data(&self) -> Result<SourceData> {
-        let data_html = self.get_data_html()?;
-        let version_html = if self.url() == self.version_url() {
-            &data_html
+        if self.inner.format == SourceFormat::NixModules && self.inner.command.is_none() {
+            let path = self.local_data_path().ok_or_else(|| {
+                eyre!(
+                    "Nix modules must be read from a local path, not {}",
+                    self.url()
+                )
+            })??;
+            let opts = parse_nix_modules(&path)?;
+            let mut data = SourceData {
+                source: self.redacted(),
+                parse_report: ParseReport::complete(opts.len()),
+                opts,
+                packages: vec![],
+                functions: vec![],
+                version: "Local modules".to_string(),
+                data_validators: HttpValidators::default(),
+                version_validators: HttpValidators::default(),
+            };
+            data.rewrite_urls();
+            return Ok(data);
+        }
+        let data = self.get_data_html()?;
+        self.parse_online_data(&data.body, data.validators)
+    }
+
+    /// Parse freshly fetched data, together with the version belonging to it
+    fn parse_online_data(
+        &self,
+        data_html: &str,
+        data_validators: HttpValidators,
+    ) -> Result<SourceData> {
+        let mut data = if self.url() == self.version_url() || self.inner.command.is_some() {
+            self.parse_data(data_html, data_html)?
         } else {
-            &self.get_version_html()?
+            let version = self.get_version_html()?;
+            let mut data = self.parse_data(data_html, &version.body)?;
+            data.version_validators = version.validators;
+            data
         };
-        self.parse_data(&data_html, version_html)
+        data.data_validators = data_validators;
+        Ok(data)
+    }
+
+    /// Refresh `cached` with conditional requests, so that unchanged data is neither downloaded nor parsed again.
+    /// Sources that are not downloaded, or whose server sent no validators, are fetched from scratch.
+    #[instrument(err, level = "debug", skip(cached))]
+    pub(crate) fn get_online_data_since(
+        &self,
+        cached: &SourceData,
+    ) -> Result<Revalidation<SourceData>> {
+        if self.inner.command.is_some()
+            || self.local_data_path().is_some()
+            || cached.data_validators.is_empty()
+        {
+            return self.get_online_data().map(Revalidation::Changed);
+        }
+        if let Some(data) = http_get(
+            self.url(),
+            &self.request_headers()?,
+            Some(&cached.data_validators),
+        )? {
+            return self
+                .parse_online_data(&data.body, data.validators)
+                .map(Revalidation::Changed);
+        }
+        debug!(source = %self, "Data not modified since it was cached");
+
+        if self.url() == self.version_url() || self.local_version_path().is_some() {
+            return Ok(Revalidation::Unchanged);
+        }
+        match http_get(
+            self.version_url(),
+            &self.request_headers()?,
+            Some(&cached.version_validators),
+        )? {
+            None => Ok(Revalidation::Unchanged),
+            // Only the version page changed, so the cached data just needs the new version
+            Some(version) => Ok(Revalidation::Changed(SourceData {
+                version: self.parse_version(&version.body).to_string(),
+                version_validators: version.validators,
+                ..cached.clone()
+            })),
+        }
+    }
+}
+
+/// The compiled `url_rewrites` of a source
+pub(crate) struct UrlRewriter(Vec<(Regex, String)>);
+
+impl UrlRewriter {
+    pub(crate) fn new(rewrites: &[UrlRewrite]) -> Self {
+        Self(
+            rewrites
+                .iter()
+                .filter_map(|rewrite| match Regex::new(&rewrite.pattern) {
+                    Ok(pattern) => Some((pattern, rewrite.replacement.clone())),
+                    // Patterns are validated when loading the config, so just skip invalid ones
+                    Err(err) => {
+                        error!("Invalid url_rewrites pattern {}: {err}", rewrite.pattern);
+                        None
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    pub(crate) fn rewrite(&self, url: &str) -> String {
+        self.0
+            .iter()
+            .fold(url.to_string(), |url, (pattern, replacement)| {
+                pattern.replace_all(&url, replacement.as_str()).into_owned()
+            })
+    }
+}
+
+/// The validators of an HTTP response, with which the server can be asked whether the resource changed since
+#[derive(Clone, Debug, Default, Encode, Decode, PartialEq)]
+pub(crate) struct HttpValidators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl HttpValidators {
+    fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// The version of a source, as found on its version page
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum SourceVersion {
+    Found(String),
+    /// The page is of another release than the one that was picked
+    Mismatch {
+        found: String,
+        expected: String,
+    },
+    NotFound,
+    /// The version page could not be parsed, with the error
+    Error(String),
+}
+
+impl fmt::Display for SourceVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Found(version) => write!(f, "{version}"),
+            Self::Mismatch { found, expected } => write!(f, "{found} (expected {expected})"),
+            Self::NotFound => write!(f, "No version number found"),
+            Self::Error(_) => write!(f, "Error parsing version"),
+        }
+    }
+}
+
+/// A page of a source, as downloaded, read from a local file or printed by a command
+#[derive(Debug)]
+pub(crate) struct Page {
+    pub(crate) body: String,
+    pub(crate) validators: HttpValidators,
+    /// The status of the response, if the page was downloaded
+    pub(crate) status: Option<StatusCode>,
+}
+
+impl Page {
+    fn local(body: String) -> Self {
+        Self {
+            body,
+            validators: HttpValidators::default(),
+            status: None,
+        }
+    }
+}
+
+/// GET `url`, conditional on it having changed since `cached` was received, if given.
+/// Returns `None` if the server responded that it did not change.
+fn http_get(
+    url: &str,
+    headers: &[(String, String)],
+    cached: Option<&HttpValidators>,
+) -> Result<Option<Page>> {
+    let mut request = ureq::get(url);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    if let Some(cached) = cached {
+        if let Some(etag) = &cached.etag {
+            request = request.header("If-None-Match", etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header("If-Modified-Since", last_modified);
+        }
+    }
+    let mut response = request.call()?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    let validators = HttpValidators {
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+    };
+    let body = response
+        .body_mut()
+        .with_config()
+        // 30 MB reading limit.
+        // The default is 10MB, but the nixos docs are 20-21MB, at least uncompressed.
+        .limit(30 * 1024 * 1024)
+        .read_to_string()?;
+    Ok(Some(Page {
+        body,
+        validators,
+        status: Some(response.status()),
+    }))
+}
+
+fn http_get_unconditional(url: &str, headers: &[(String, String)]) -> Result<Page> {
+    http_get(url, headers, None)?
+        .ok_or_else(|| eyre!("{url} responded Not Modified to an unconditional request"))
+}
+
+/// `file://` URLs and anything without a URL scheme are read from the local filesystem.
+fn local_path(url: &str) -> Option<PathBuf> {
+    if let Some(path) = url.strip_prefix("file://") {
+        Some(PathBuf::from(path))
+    } else if url.contains("://") {
+        None
+    } else {
+        Some(PathBuf::from(url))
+    }
+}
+
+/// Run `command` in a shell and return its standard output, killing it if it runs for longer than `timeout`.
+fn run_command(command: &str, timeout: Duration) -> Result<String> {
+    let (shell, flag) = if cfg!(target_family = "windows") {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut child = Command::new(shell)
+        .args([flag, command])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Read output in separate threads so the command can't block on a full pipe while we wait for it
+    let mut stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| eyre!("Missing stdout handle"))?;
+    let mut stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| eyre!("Missing stderr handle"))?;
+    let stdout = std::thread::spawn(move || {
+        let mut out = String::new();
+        stdout.read_to_string(&mut out).map(|_| out)
+    });
+    let stderr = std::thread::spawn(move || {
+        let mut err = String::new();
+        stderr.read_to_string(&mut err).map(|_| err)
+    });
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() > timeout {
+            child.kill()?;
+            child.wait()?;
+            return Err(eyre!("Command `{command}` timed out after {timeout:?}"));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+
+    let join_err = |_| eyre!("Failed to read output of command `{command}`");
+    let stdout = stdout.join().map_err(join_err)??;
+    let stderr = stderr.join().map_err(join_err)??;
+    ensure!(
+        status.success(),
+        "Command `{command}` failed with {status}: {}",
+        stderr.trim()
+    );
+    Ok(stdout)
+}
+
+/// If `path` is a directory, such as the `share/doc/nixos` output of a documentation build, look for the first of `candidates` inside it.
+fn resolve_local_path(path: PathBuf, candidates: &[&str]) -> Result<PathBuf> {
+    if !path.is_dir() {
+        return Ok(path);
     }
+    candidates
+        .iter()
+        .map(|c| path.join(c))
+        .find(|p| p.is_file())
+        .ok_or_else(|| {
+            eyre!(
+                "Directory {} contains none of {candidates:?}",
+                path.display()
+            )
+        })
 }
 
 impl fmt::Display for Source {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.inner.name)
+        match &self.release {
+            Some(release) => write!(f, "{} {release}", self.inner.name),
+            None => write!(f, "{}", self.inner.name),
+        }
     }
 }
 
 #[derive(Clone, Debug, Encode, Decode, PartialEq)]
 pub(crate) struct SourceData {
+    /// The source the data is from, [`Source::redacted`] since it is cached
     pub(crate) source: Source,
     pub(crate) opts: Vec<OptText>,
+    pub(crate) packages: Vec<PkgText>,
+    pub(crate) functions: Vec<FunText>,
     pub(crate) version: String,
+    pub(crate) parse_report: ParseReport,
+    /// Used to revalidate the cache of downloaded data, instead of downloading it again
+    pub(crate) data_validators: HttpValidators,
+    pub(crate) version_validators: HttpValidators,
 }
 
 impl SourceData {
-    fn nixos_unstable_declared_by_hack(&mut self) {
-        if self.source.inner.name == "NixOS Unstable" {
-            for opt in &mut self.opts {
-                opt.declared_by_urls = opt
-                    .declared_by_urls
-                    .iter()
-                    .map(|url| {
-                        regex_replace_all!(r#"release-\d{2}\.\d{2}"#, url, "nixos-unstable")
-                            .to_string()
-                    })
-                    .collect();
+    /// All options, packages and functions of the source
+    pub(crate) fn into_entries(self) -> impl Iterator<Item = Entry> {
+        self.opts
+            .into_iter()
+            .map(Entry::Opt)
+            .chain(self.packages.into_iter().map(Entry::Pkg))
+            .chain(self.functions.into_iter().map(Entry::Fun))
+    }
+
+    pub(crate) fn entry_count(&self) -> usize {
+        self.opts.len() + self.packages.len() + self.functions.len()
+    }
+
+    /// Resolve the links in descriptions, which are relative to the documentation page, and apply the `url_rewrites` of the source to them and to the links to the source code of each entry
+    fn rewrite_urls(&mut self) {
+        let source = &self.source;
+        let rewriter = UrlRewriter::new(&source.inner.url_rewrites);
+        let links = self
+            .opts
+            .iter_mut()
+            .flat_map(|opt| {
+                opt.rich_description
+                    .links_mut()
+                    .chain(opt.rich_example.links_mut())
+            })
+            .chain(self.functions.iter_mut().flat_map(|fun| {
+                fun.rich_description
+                    .links_mut()
+                    .chain(fun.rich_example.links_mut())
+            }));
+        for link in links {
+            if let Some(resolved) = source.resolve_link(link) {
+                *link = resolved;
             }
+            *link = rewriter.rewrite(link);
+        }
+        if rewriter.0.is_empty() {
+            return;
+        }
+        let urls = self
+            .opts
+            .iter_mut()
+            .flat_map(|opt| opt.declared_by_urls.iter_mut())
+            .chain(
+                self.functions
+                    .iter_mut()
+                    .flat_map(|fun| fun.declared_by_urls.iter_mut()),
+            )
+            .chain(
+                self.packages
+                    .iter_mut()
+                    .filter_map(|pkg| pkg.position_url.as_mut()),
+            );
+        for url in urls {
+            *url = rewriter.rewrite(url);
         }
     }
 }
@@ -139,7 +718,7 @@ impl SourceData {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_utils::BUILTIN_SOURCES_WITH_HTML;
+    use crate::test_utils::{local_options_source, outdated, BUILTIN_SOURCES_WITH_HTML};
     use std::path::PathBuf;
     use tempfile::tempdir;
 
@@ -157,12 +736,593 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_local_options_json() -> Result<()> {
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        let path = tmpdir.path().join("options.json");
+        std::fs::write(
+            &path,
+            r#"{"programs.foo.enable": {"type": "boolean", "default": false, "description": "Whether to enable foo.", "declarations": []}}"#,
+        )?;
+        let source = Source::from(&SourceConfig {
+            name: "Local".to_string(),
+            url: path.display().to_string(),
+            format: SourceFormat::OptionsJson,
+            ..Default::default()
+        });
+        let data = source.get_online_data()?;
+        assert_eq!(data.opts.len(), 1);
+        assert_eq!(data.opts[0].name, "programs.foo.enable");
+        assert_eq!(data.opts[0].var_type, "boolean");
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_schema() -> Result<()> {
+        use crate::cache::{CacheConfig, CacheMismatch};
+
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        let source = Source::from(&local_options_source(tmpdir.path()));
+        let cache_file = tmpdir.path().join("Local.zst");
+        let data = source.get_online_data()?;
+        Source::store_cache(&data, &cache_file)?;
+        assert_eq!(Source::load_cache(&cache_file)?, data);
+
+        // Caches of another schema and from before cache files had headers are refetched
+        let mut other_schema = std::fs::read(&cache_file)?;
+        other_schema[4] += 1;
+        std::fs::write(&cache_file, other_schema)?;
+        let err = Source::load_cache(&cache_file).expect_err("Schema mismatch is an error");
+        assert!(matches!(
+            err.downcast_ref::<CacheMismatch>(),
+            Some(CacheMismatch::Schema { found, expected })
+                if *expected == Source::CACHE_SCHEMA && found.schema == Source::CACHE_SCHEMA + 1
+        ));
+        let config = CacheConfig {
+            file: Some(cache_file.clone()),
+            ..CacheConfig::default()
+        };
+        assert_eq!(source.get_data(&config)?, data);
+        assert_eq!(Source::load_cache(&cache_file)?, data);
+
+        let headerless = zstd::stream::encode_all(bitcode::encode(&data).as_slice(), 0)?;
+        std::fs::write(&cache_file, headerless)?;
+        let err = Source::load_cache(&cache_file).expect_err("Missing header is an error");
+        assert_eq!(
+            err.downcast_ref::<CacheMismatch>(),
+            Some(&CacheMismatch::NoHeader)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_locked_by_other_process() -> Result<()> {
+        use crate::cache::{CacheConfig, CacheLock};
+
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        let source = Source::from(&local_options_source(tmpdir.path()));
+        let cache_file = tmpdir.path().join("Local.zst");
+        let cached = source.get_online_data()?;
+        Source::store_cache(&cached, &cache_file)?;
+        outdated(&cache_file)?;
+        std::fs::write(
+            tmpdir.path().join("options.json"),
+            r#"{"programs.bar.enable": {"type": "boolean"}}"#,
+        )?;
+        let config = CacheConfig {
+            file: Some(cache_file.clone()),
+            ..CacheConfig::default()
+        };
+
+        // While another process refreshes the cache, the outdated cache is used
+        let lock = CacheLock::try_acquire(&cache_file)?.expect("The lock is free");
+        assert_eq!(source.get_data(&config)?, cached);
+        drop(lock);
+        assert_eq!(
+            source.get_data(&config)?.opts[0].name,
+            "programs.bar.enable"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_offline() -> Result<()> {
+        use crate::cache::{CacheConfig, Offline};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let local = Source::from(&SourceConfig {
+            name: "Local".to_string(),
+            url: "data/options.json".to_string(),
+            format: SourceFormat::OptionsJson,
+            ..Default::default()
+        });
+        let remote = Source::from(&SourceConfig {
+            name: "Remote".to_string(),
+            url: format!("http://{}/options.json", listener.local_addr()?),
+            format: SourceFormat::OptionsJson,
+            ..Default::default()
+        });
+        assert!(!local.needs_network());
+        assert!(remote.needs_network());
+
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        let cache_file = tmpdir.path().join("Remote.zst");
+        let config = CacheConfig {
+            file: Some(cache_file.clone()),
+            system_files: vec![],
+            duration: Some(Duration::from_secs(60)),
+            offline: true,
+        };
+        let err = remote
+            .get_data(&config)
+            .expect_err("Uncached data can't be loaded offline");
+        assert!(err.is::<Offline>(), "{err:?}");
+
+        // Outdated caches are used as they are
+        Source::store_cache(&empty_data(&remote, "cached"), &cache_file)?;
+        outdated(&cache_file)?;
+        let mut shown = vec![];
+        remote.get_data_stale_while_revalidate(&config, &mut |data, stale| {
+            shown.push((data.version, stale));
+        })?;
+        assert_eq!(shown, [("cached".to_string(), true)]);
+        assert_eq!(
+            listener.accept().map_err(|e| e.kind()).err(),
+            Some(std::io::ErrorKind::WouldBlock),
+            "Nothing was downloaded"
+        );
+        Ok(())
+    }
+
+    fn empty_data(source: &Source, version: &str) -> SourceData {
+        SourceData {
+            source: source.clone(),
+            opts: vec![],
+            packages: vec![],
+            functions: vec![],
+            version: version.to_string(),
+            parse_report: ParseReport::default(),
+            data_validators: HttpValidators::default(),
+            version_validators: HttpValidators::default(),
+        }
+    }
+
+    #[test]
+    fn test_system_cache() -> Result<()> {
+        use crate::cache::{CacheConfig, CacheStatus};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let source = Source::from(&SourceConfig {
+            name: "Remote".to_string(),
+            url: format!("http://{}/options.json", listener.local_addr()?),
+            format: SourceFormat::OptionsJson,
+            ..Default::default()
+        });
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        let own_file = source.cache_file(&tmpdir.path().join("own"));
+        let system_dir = tmpdir.path().join("system");
+        std::fs::create_dir(&system_dir)?;
+        let system_file = source.cache_file(&system_dir);
+        Source::store_cache(&empty_data(&source, "system"), &system_file)?;
+        let config = CacheConfig {
+            file: Some(own_file.clone()),
+            system_files: vec![system_file.clone()],
+            duration: Some(Duration::from_secs(60)),
+            offline: false,
+        };
+
+        // A fresh system cache is used as it is
+        assert_eq!(source.get_data(&config)?.version, "system");
+        // Caches in the Nix store have no age, and count as fresh
+        std::fs::File::options()
+            .write(true)
+            .open(&system_file)?
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1))?;
+        assert_eq!(source.get_data(&config)?.version, "system");
+        assert!(!own_file.exists(), "System caches are not copied");
+        assert_eq!(
+            listener.accept().map_err(|e| e.kind()).err(),
+            Some(std::io::ErrorKind::WouldBlock),
+            "Nothing was downloaded"
+        );
+
+        // A fresh cache of its own is preferred
+        std::fs::create_dir(tmpdir.path().join("own"))?;
+        Source::store_cache(&empty_data(&source, "own"), &own_file)?;
+        assert_eq!(source.get_data(&config)?.version, "own");
+
+        // Only system caches count as fresh without an age, a restored cache of its own is just old
+        std::fs::File::options()
+            .write(true)
+            .open(&own_file)?
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1))?;
+        assert!(matches!(
+            source.cache_status(&config)?,
+            CacheStatus::Outdated
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_url_rewrites() -> Result<()> {
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        let path = tmpdir.path().join("options.json");
+        std::fs::write(
+            &path,
+            r#"{"programs.foo.enable": {"type": "boolean", "declarations": [{"name": "<nixpkgs/foo.nix>", "url": "https://github.com/NixOS/nixpkgs/blob/release-25.11/foo.nix"}]}}"#,
+        )?;
+        let source = Source::from(&SourceConfig {
+            name: "Renamed tab".to_string(),
+            url: path.display().to_string(),
+            format: SourceFormat::OptionsJson,
+            url_rewrites: vec![
+                UrlRewrite {
+                    pattern: r"release-\d{2}\.\d{2}".to_string(),
+                    replacement: "nixos-unstable".to_string(),
+                },
+                UrlRewrite {
+                    pattern: r"^https://github\.com/NixOS/(\w+)/blob/".to_string(),
+                    replacement: "https://git.example.com/mirrors/$1/-/blob/".to_string(),
+                },
+                UrlRewrite {
+                    pattern: "^file://".to_string(),
+                    replacement: "https://docs.example.com".to_string(),
+                },
+            ],
+            ..Default::default()
+        });
+        let data = source.get_online_data()?;
+        assert_eq!(
+            data.opts[0].declared_by_urls,
+            vec!["https://git.example.com/mirrors/nixpkgs/-/blob/nixos-unstable/foo.nix"]
+        );
+        let entry = data.into_entries().next().expect("Has one entry");
+        assert_eq!(
+            source.doc_url_to(&entry),
+            format!(
+                "https://docs.example.com{}#opt-programs.foo.enable",
+                std::fs::canonicalize(&path)?.display()
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_release_template() -> Result<()> {
+        use crate::test_utils::OPTIONS_JSON;
+
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        // The 24.05 directory accidentally contains the manual of 24.11
+        for (release, version) in [("24.05", "24.11.99"), ("24.11", "24.11.1234")] {
+            let dir = tmpdir.path().join(release);
+            std::fs::create_dir(&dir)?;
+            std::fs::write(dir.join("options.json"), OPTIONS_JSON)?;
+            std::fs::write(
+                dir.join("index.html"),
+                format!("<html><head><title>NixOS {version} manual</title></head></html>"),
+            )?;
+        }
+        let config = SourceConfig {
+            name: "NixOS".to_string(),
+            url: format!("{}/{{version}}/options.json", tmpdir.path().display()),
+            version_url: Some(format!(
+                "{}/{{version}}/index.html",
+                tmpdir.path().display()
+            )),
+            format: SourceFormat::OptionsJson,
+            releases: vec!["24.05".to_string(), "24.11".to_string()],
+            ..Default::default()
+        };
+
+        let default = Source::from(&config);
+        assert_eq!(default.to_string(), "NixOS 24.05");
+        assert_eq!(default.releases(), ["24.05", "24.11"]);
+        assert_eq!(
+            default.get_online_data()?.version,
+            "Version 24.11.99 (expected 24.05)"
+        );
+        let version_html = std::fs::read_to_string(tmpdir.path().join("24.05/index.html"))?;
+        assert_eq!(
+            default.parse_version(&version_html),
+            SourceVersion::Mismatch {
+                found: "Version 24.11.99".to_string(),
+                expected: "24.05".to_string(),
+            }
+        );
+
+        let picked = Source::with_release(&config, Some("24.11"));
+        assert_eq!(picked.to_string(), "NixOS 24.11");
+        assert!(picked.url().ends_with("/24.11/options.json"));
+        let data = picked.get_online_data()?;
+        assert_eq!(data.version, "Version 24.11.1234");
+        assert_eq!(data.opts.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_packages_json() -> Result<()> {
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        std::fs::write(
+            tmpdir.path().join("packages.json"),
+            r#"{"version": 2, "packages": {"hello": {"name": "hello-2.12.1", "pname": "hello", "version": "2.12.1", "meta": {"homepage": "https://www.gnu.org/software/hello/manual/"}}}}"#,
+        )?;
+        let source = Source::from(&SourceConfig {
+            name: "Packages".to_string(),
+            url: tmpdir.path().display().to_string(),
+            format: SourceFormat::PackagesJson,
+            ..Default::default()
+        });
+        let data = source.get_online_data()?;
+        assert!(data.opts.is_empty());
+        assert_eq!(data.packages.len(), 1);
+        let entry = data.into_entries().next().expect("Has one entry");
+        assert_eq!(entry.name(), "hello");
+        assert_eq!(
+            source.doc_url_to(&entry),
+            "https://www.gnu.org/software/hello/manual/"
+        );
+
+        // Package positions link to the branch of the channel the packages are from
+        assert_eq!(source.nixpkgs_revision(), "master");
+        let channel = Source::from(&SourceConfig {
+            url: "https://channels.nixos.org/nixos-24.11/packages.json.br".to_string(),
+            ..source.inner.clone()
+        });
+        assert_eq!(channel.nixpkgs_revision(), "nixos-24.11");
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_nix_modules() -> Result<()> {
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        let module = tmpdir.path().join("foo.nix");
+        std::fs::write(
+            &module,
+            "{ lib, ... }:\n{\n  options.programs.foo.enable = lib.mkEnableOption \"foo\";\n}\n",
+        )?;
+        let source = Source::from(&SourceConfig {
+            name: "Modules".to_string(),
+            url: tmpdir.path().display().to_string(),
+            format: SourceFormat::NixModules,
+            ..Default::default()
+        });
+        assert!(source.last_modified().is_some());
+        let data = source.get_online_data()?;
+        assert_eq!(data.opts.len(), 1);
+        let entry = data.into_entries().next().expect("Has one entry");
+        assert_eq!(entry.name(), "programs.foo.enable");
+        assert_eq!(
+            source.doc_url_to(&entry),
+            format!("file://{}#L3", std::fs::canonicalize(&module)?.display())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_conditional_revalidation() -> Result<()> {
+        use crate::cache::CacheConfig;
+        use crate::test_utils::{ok_response, serve, OPTIONS_JSON};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        // Serves options with an ETag, and responds Not Modified to requests that already have it
+        let full_responses = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&full_responses);
+        let url = serve(move |headers| {
+            if headers
+                .iter()
+                .any(|h| h.to_lowercase() == "if-none-match: \"v1\"")
+            {
+                "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".to_string()
+            } else {
+                counter.fetch_add(1, Ordering::SeqCst);
+                ok_response("ETag: \"v1\"\r\n", OPTIONS_JSON)
+            }
+        })?;
+
+        let source = Source::from(&SourceConfig {
+            name: "Remote".to_string(),
+            url,
+            format: SourceFormat::OptionsJson,
+            ..Default::default()
+        });
+        let data = source.get_online_data()?;
+        assert_eq!(data.data_validators.etag.as_deref(), Some("\"v1\""));
+        assert!(matches!(
+            source.get_online_data_since(&data)?,
+            Revalidation::Unchanged
+        ));
+
+        // An outdated cache is extended instead of downloaded again
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        let cache_file = tmpdir.path().join("Remote.zst");
+        Source::store_cache(&data, &cache_file)?;
+        let long_ago = outdated(&cache_file)?;
+        let cache_config = CacheConfig {
+            file: Some(cache_file.clone()),
+            system_files: vec![],
+            duration: Some(Duration::from_secs(60)),
+            offline: false,
+        };
+        assert_eq!(source.get_data(&cache_config)?, data);
+        assert!(cache_file.metadata()?.modified()? > long_ago);
+        assert_eq!(full_responses.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_request_headers() -> Result<()> {
+        use crate::config::HttpHeader;
+        use crate::test_utils::{ok_response, serve, OPTIONS_JSON};
+
+        // Only serves options to requests with the token
+        let url = serve(|headers| {
+            if headers.iter().any(|h| h == "authorization: Bearer s3cret") {
+                ok_response("", OPTIONS_JSON)
+            } else {
+                "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string()
+            }
+        })?;
+
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        let token_file = tmpdir.path().join("token");
+        std::fs::write(&token_file, "s3cret\n")?;
+        let mut config = SourceConfig {
+            name: "Private".to_string(),
+            url,
+            format: SourceFormat::OptionsJson,
+            ..Default::default()
+        };
+        assert!(Source::from(&config).get_online_data().is_err());
+
+        config.headers = vec![HttpHeader {
+            name: "Authorization".to_string(),
+            value: Some("Bearer ".to_string()),
+            value_file: Some(token_file.display().to_string()),
+            ..Default::default()
+        }];
+        let source = Source::from(&config);
+        assert_eq!(source.get_online_data()?.opts.len(), 1);
+        assert!(!format!("{source:?}").contains("Bearer"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_values_not_cached() -> Result<()> {
+        use crate::cache::CacheValidity;
+        use crate::config::HttpHeader;
+
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        let source = Source::from(&SourceConfig {
+            headers: vec![HttpHeader {
+                name: "Authorization".to_string(),
+                value: Some("Bearer s3cret".to_string()),
+                ..Default::default()
+            }],
+            ..local_options_source(tmpdir.path())
+        });
+        let cache_file = tmpdir.path().join("Local.zst");
+        Source::store_cache(&source.get_online_data()?, &cache_file)?;
+
+        let cached = Source::load_cache(&cache_file)?;
+        assert_eq!(cached.source.inner.headers[0].name, "Authorization");
+        assert_eq!(cached.source.inner.headers[0].value, None);
+        assert!(matches!(source.cache_valid(&cached), CacheValidity::Good));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn test_command_source() -> Result<()> {
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        let script = tmpdir.path().join("options.sh");
+        std::fs::write(
+            &script,
+            r#"#!/bin/sh
+echo "building options" >&2
+echo '{"programs.foo.enable": {"type": "boolean", "description": "Whether to enable foo."}}'
+"#,
+        )?;
+        let source = Source::from(&SourceConfig {
+            name: "Command".to_string(),
+            url: "https://example.com/docs".to_string(),
+            format: SourceFormat::OptionsJson,
+            command: Some(format!("sh {}", script.display())),
+            ..Default::default()
+        });
+        let data = source.get_online_data()?;
+        assert_eq!(data.opts.len(), 1);
+        assert_eq!(data.opts[0].name, "programs.foo.enable");
+        assert!(source.last_modified().is_none());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn test_command_source_errors() {
+        let failing = Source::from(&SourceConfig {
+            command: Some("echo 'no such flake' >&2; exit 1".to_string()),
+            format: SourceFormat::OptionsJson,
+            ..Default::default()
+        });
+        let err = failing
+            .get_online_data()
+            .expect_err("Failing command is an error")
+            .to_string();
+        assert!(err.contains("no such flake"), "{err}");
+
+        let hanging = Source::from(&SourceConfig {
+            command: Some("sleep 10".to_string()),
+            command_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        let err = hanging
+            .get_online_data()
+            .expect_err("Hanging command times out")
+            .to_string();
+        assert!(err.contains("timed out"), "{err}");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_local_directory_source_freshness() -> Result<()> {
+        use crate::cache::{CacheConfig, CacheStatus};
+
+        let tmpdir = tempdir().expect("Can create temporary directory");
+        let json_path = tmpdir.path().join("options.json");
+        std::fs::write(
+            &json_path,
+            r#"{"programs.foo.enable": {"type": "boolean", "description": "Whether to enable foo."}}"#,
+        )?;
+        let source = Source::from(&SourceConfig {
+            name: "Local".to_string(),
+            url: format!("file://{}", tmpdir.path().display()),
+            format: SourceFormat::OptionsJson,
+            ..Default::default()
+        });
+        let cache_config = CacheConfig {
+            file: Some(tmpdir.path().join("Local.zst")),
+            system_files: vec![],
+            duration: Some(Duration::from_secs(7 * 24 * 60 * 60)),
+            offline: false,
+        };
+
+        assert!(matches!(
+            source.cache_status(&cache_config)?,
+            CacheStatus::Missing
+        ));
+        let data = source.get_data(&cache_config)?;
+        assert_eq!(data.opts.len(), 1);
+        assert!(matches!(
+            source.cache_status(&cache_config)?,
+            CacheStatus::Fresh
+        ));
+
+        // Modifying the source file outdates the cache, even though the cache duration has not passed
+        let later = SystemTime::now() + Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(&json_path)?
+            .set_modified(later)?;
+        assert!(matches!(
+            source.cache_status(&cache_config)?,
+            CacheStatus::Outdated
+        ));
+        Ok(())
+    }
+
     #[test]
     fn test_doc_urls_trimmed() {
         // Previously, Source::url_to returned urls with a trailing newline. Still not sure where the newline originates.
         for swh in BUILTIN_SOURCES_WITH_HTML.iter() {
-            for opt in &swh.data.opts {
-                let url = swh.source.doc_url_to(opt);
+            for entry in swh.data.clone().into_entries() {
+                let url = swh.source.doc_url_to(&entry);
                 assert_eq!(url, url.trim());
                 assert_ne!(url.chars().last(), Some('\n'));
             }
@@ -1,17 +1,21 @@
 /// Test utilities to handle in-repo test data, and tests to verify that this data is kept in sync with code.
 /// The test data in question is acquired by the `update_sources.py` script. This module should, in addition to defining utility functions to read the test data and make it available to tests in other modules, also run tests of that data itself to catch possible errors or failures of the data acquisition script.
 use crate::{
+    cache::CacheConfig,
     config::{
         consts::{self, BUILTIN_SOURCES},
-        SourceConfig,
+        SourceConfig, SourceFormat,
     },
     finder::Finder,
     source::{Source, SourceData},
 };
 use std::{
     fs,
+    io::{self, BufRead, BufReader, Write},
+    net::TcpListener,
     path::{Path, PathBuf},
     sync::LazyLock,
+    time::{Duration, SystemTime},
 };
 
 pub(crate) struct SourceWithHTML {
@@ -173,7 +177,7 @@ fn read_source_html_from_testdata(se: SourceExpectations, base_dir: &Path) -> So
 pub fn create_test_finder(source: &Source, data: &SourceData) -> Finder {
     let data = data.clone();
     let data_fn = Box::new(move || Ok(data));
-    Finder::new_with_data_fn(source.clone(), Some(data_fn), None, None)
+    Finder::new_with_data_fn(source.clone(), Some(data_fn), CacheConfig::default())
 }
 
 /// Create test finders for all builtin sources
@@ -184,6 +188,57 @@ pub fn create_test_finders() -> Vec<Finder> {
         .collect()
 }
 
+/// An `options.json` with a single option
+pub(crate) const OPTIONS_JSON: &str = r#"{"programs.foo.enable": {"type": "boolean"}}"#;
+
+/// A source named "Local" that reads [`OPTIONS_JSON`], which is written to `dir`
+pub(crate) fn local_options_source(dir: &Path) -> SourceConfig {
+    let path = dir.join("options.json");
+    fs::write(&path, OPTIONS_JSON).expect("Can write test data");
+    SourceConfig {
+        name: "Local".to_string(),
+        url: path.display().to_string(),
+        format: SourceFormat::OptionsJson,
+        ..Default::default()
+    }
+}
+
+/// Backdates `file` by an hour, so that it is outdated for any shorter cache duration. Returns its new modification time.
+pub(crate) fn outdated(file: &Path) -> io::Result<SystemTime> {
+    let modified = SystemTime::now() - Duration::from_secs(60 * 60);
+    fs::File::options()
+        .write(true)
+        .open(file)?
+        .set_modified(modified)?;
+    Ok(modified)
+}
+
+/// Serves HTTP on localhost in the background, answering every request with the raw response `respond` makes from its header lines.
+/// Returns the URL of `options.json` on the server.
+pub(crate) fn serve(respond: impl Fn(&[String]) -> String + Send + 'static) -> io::Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/options.json", listener.local_addr()?);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let headers = BufReader::new(&stream)
+                .lines()
+                .map_while(Result::ok)
+                .take_while(|line| !line.is_empty())
+                .collect::<Vec<_>>();
+            drop((&stream).write_all(respond(&headers).as_bytes()));
+        }
+    });
+    Ok(url)
+}
+
+/// A `200 OK` response with `body`, after the header lines in `headers`
+pub(crate) fn ok_response(headers: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
 #[test]
 fn verify_all_builtin_sources_tested() {
     assert_eq!(BUILTIN_SOURCES.len(), BUILTIN_SOURCES_EXPECTATIONS.len());
@@ -205,7 +260,7 @@ fn verify_builtin_sources_expectations() {
         eprintln!("Source: {}", swh.source);
         eprintln!("data_html.len(): {:?}", swh.data_html.len());
         eprintln!("version_html.len(): {}", swh.version_html.len());
-        eprintln!("data.opts.len(): {}", swh.data.opts.len());
+        eprintln!("data.entry_count(): {}", swh.data.entry_count());
         eprintln!();
     }
     for swh in BUILTIN_SOURCES_WITH_HTML.iter() {
@@ -214,9 +269,9 @@ fn verify_builtin_sources_expectations() {
         assert!(swh.data_html.len() >= swh.expectations.min_data_html_length, "Assertion failed: swh.data_html.len() ({}) >= swh.expectations.min_data_html_length ({})", swh.data_html.len(),  swh.expectations.min_data_html_length);
         assert!(swh.version_html.len() >= swh.expectations.min_version_html_length, "Assertion failed: swh.version_html.len() ({}) >= swh.expectations.min_version_html_length ({})", swh.version_html.len(),  swh.expectations.min_version_html_length);
         assert!(
-            swh.data.opts.len() >= swh.expectations.min_parsed_terms,
-            "Assertion failed: swh.data.opts.len() ({}) >= swh.expectations.min_parsed_terms ({})",
-            swh.data.opts.len(),
+            swh.data.entry_count() >= swh.expectations.min_parsed_terms,
+            "Assertion failed: swh.data.entry_count() ({}) >= swh.expectations.min_parsed_terms ({})",
+            swh.data.entry_count(),
             swh.expectations.min_parsed_terms
         );
         eprintln!("Expectations for {} verified", swh.source);
@@ -1,4 +1,5 @@
 use std::io::{self, stdout, Stdout};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use crossterm::{
     execute,
@@ -11,9 +12,13 @@ use color_eyre::eyre::Result;
 /// A type alias for the terminal type used in this application
 pub type Tui = Terminal<CrosstermBackend<Stdout>>;
 
+/// Whether the terminal was initialized, so that subcommands printing to stdout don't get escape codes appended to their output
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
 /// Initialize the terminal
 pub fn init() -> io::Result<Tui> {
     initialize_panic_handler();
+    ACTIVE.store(true, Ordering::Relaxed);
     execute!(stdout(), EnterAlternateScreen)?;
     enable_raw_mode()?;
     Terminal::new(CrosstermBackend::new(stdout()))
@@ -21,6 +26,9 @@ pub fn init() -> io::Result<Tui> {
 
 /// Restore the terminal to its original state
 pub fn restore() -> Result<()> {
+    if !ACTIVE.swap(false, Ordering::Relaxed) {
+        return Ok(());
+    }
     execute!(stdout(), LeaveAlternateScreen)?;
     disable_raw_mode()?;
     Ok(())